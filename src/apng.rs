@@ -0,0 +1,361 @@
+use crate::{
+    bits,
+    errors::Error,
+    known_chunk::{FrameControl, KnownChunk},
+    png::Png,
+};
+
+// Frame timing payloads are prefixed with their length in bytes.
+const LENGTH_PREFIX_BITS: u32 = 16;
+const MAX_BITS_PER_FRAME: u32 = 16;
+
+#[derive(Debug)]
+pub struct Frame {
+    pub control: FrameControl,
+    pub data_chunks: usize,
+    pub is_default_image: bool,
+}
+
+#[derive(Debug)]
+pub struct Animation {
+    pub num_frames: u32,
+    pub num_plays: u32,
+    pub frames: Vec<Frame>,
+    sequence_numbers: Vec<u32>,
+}
+
+impl Animation {
+    pub fn from_png(png: &Png) -> Result<Option<Animation>, Error> {
+        let mut control = None;
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut sequence_numbers = Vec::new();
+        let mut seen_idat = false;
+
+        for chunk in png.chunks() {
+            match chunk.chunk_type().to_string().as_str() {
+                "acTL" => {
+                    if let KnownChunk::Actl {
+                        num_frames,
+                        num_plays,
+                    } = chunk.parse_known()?
+                    {
+                        control = Some((num_frames, num_plays));
+                    }
+                }
+                "fcTL" => {
+                    if let KnownChunk::Fctl(fctl) = chunk.parse_known()? {
+                        sequence_numbers.push(fctl.sequence_number);
+                        frames.push(Frame {
+                            control: fctl,
+                            data_chunks: 0,
+                            is_default_image: !seen_idat,
+                        });
+                    }
+                }
+                "IDAT" => {
+                    seen_idat = true;
+                    if let Some(frame) = frames.last_mut().filter(|frame| frame.is_default_image) {
+                        frame.data_chunks += 1;
+                    }
+                }
+                "fdAT" => {
+                    if let KnownChunk::Fdat {
+                        sequence_number, ..
+                    } = chunk.parse_known()?
+                    {
+                        sequence_numbers.push(sequence_number);
+                        let frame = frames.last_mut().ok_or(Error::InvalidAnimation(
+                            "fdAT chunk before any fcTL".to_string(),
+                        ))?;
+                        frame.data_chunks += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(control.map(|(num_frames, num_plays)| Animation {
+            num_frames,
+            num_plays,
+            frames,
+            sequence_numbers,
+        }))
+    }
+
+    pub fn validate(&self, png: &Png) -> Result<(), Error> {
+        if self.num_frames as usize != self.frames.len() {
+            return Err(Error::InvalidAnimation(format!(
+                "acTL declares {} frames but {} fcTL chunks were found",
+                self.num_frames,
+                self.frames.len()
+            )));
+        }
+
+        for (expected, &actual) in self.sequence_numbers.iter().enumerate() {
+            if expected as u32 != actual {
+                return Err(Error::InvalidAnimation(format!(
+                    "sequence number {} found where {} was expected",
+                    actual, expected
+                )));
+            }
+        }
+
+        let header = png.image_header()?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let control = &frame.control;
+            if control.width == 0
+                || control.height == 0
+                || control.x_offset as u64 + control.width as u64 > header.width as u64
+                || control.y_offset as u64 + control.height as u64 > header.height as u64
+            {
+                return Err(Error::InvalidAnimation(format!(
+                    "frame {} lies outside the {}x{} canvas",
+                    index, header.width, header.height
+                )));
+            }
+            if frame.data_chunks == 0 {
+                return Err(Error::InvalidAnimation(format!(
+                    "frame {} has no image data",
+                    index
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn timing_capacity(png: &Png) -> Result<usize, Error> {
+    let bits: u32 = frame_controls(png)?
+        .iter()
+        .map(|(_, fctl)| bits_for_frame(fctl))
+        .sum();
+
+    Ok(bits.saturating_sub(LENGTH_PREFIX_BITS) as usize / 8)
+}
+
+pub fn embed_in_frame_timing(png: &mut Png, data: &[u8]) -> Result<(), Error> {
+    let available = timing_capacity(png)?;
+    if data.len() > available {
+        return Err(Error::CapacityExceeded {
+            needed: data.len(),
+            available,
+        });
+    }
+
+    let mut payload: Vec<bool> =
+        bits::bits_of_value(data.len() as u32, LENGTH_PREFIX_BITS).collect();
+    payload.extend(bits::bits_of(data));
+    let mut payload = payload.into_iter();
+
+    for (index, mut fctl) in frame_controls(png)? {
+        let width = bits_for_frame(&fctl);
+        let value: Vec<bool> = payload.by_ref().take(width as usize).collect();
+        let value = bits::value_from_bits(&value) << (width as usize - value.len());
+
+        let (num, den) = reduced_delay(&fctl);
+        let factor = value as u16 + 1;
+        fctl.delay_num = num * factor;
+        fctl.delay_den = den * factor;
+        png.chunks_mut()[index] = KnownChunk::Fctl(fctl).to_chunk();
+    }
+
+    Ok(())
+}
+
+pub fn extract_from_frame_timing(png: &Png) -> Result<Vec<u8>, Error> {
+    let mut payload = Vec::new();
+
+    for (_, fctl) in frame_controls(png)? {
+        let den = if fctl.delay_den == 0 {
+            100
+        } else {
+            fctl.delay_den
+        };
+        let factor = gcd(fctl.delay_num, den) as u32;
+        payload.extend(bits::bits_of_value(factor - 1, bits_for_frame(&fctl)));
+    }
+
+    if payload.len() < LENGTH_PREFIX_BITS as usize {
+        return Err(Error::NoHiddenData);
+    }
+    let (length, payload) = payload.split_at(LENGTH_PREFIX_BITS as usize);
+    let length = bits::value_from_bits(length) as usize;
+    if length == 0 || length * 8 > payload.len() {
+        return Err(Error::NoHiddenData);
+    }
+
+    Ok(bits::bytes_from_bits(&payload[..length * 8]))
+}
+
+fn frame_controls(png: &Png) -> Result<Vec<(usize, FrameControl)>, Error> {
+    let mut controls = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().to_string() == "fcTL" {
+            if let KnownChunk::Fctl(fctl) = chunk.parse_known()? {
+                controls.push((index, fctl));
+            }
+        }
+    }
+
+    if controls.is_empty() {
+        return Err(Error::InvalidAnimation(
+            "image has no fcTL chunks".to_string(),
+        ));
+    }
+    Ok(controls)
+}
+
+fn reduced_delay(fctl: &FrameControl) -> (u16, u16) {
+    let den = if fctl.delay_den == 0 {
+        100
+    } else {
+        fctl.delay_den
+    };
+    let divisor = gcd(fctl.delay_num, den);
+    (fctl.delay_num / divisor, den / divisor)
+}
+
+// Any multiple of the reduced delay fraction displays identically, so the
+// multiplier itself carries the hidden bits.
+fn bits_for_frame(fctl: &FrameControl) -> u32 {
+    let (num, den) = reduced_delay(fctl);
+    let max_factor = u16::MAX / num.max(den);
+    max_factor.ilog2().min(MAX_BITS_PER_FRAME)
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::known_chunk::{ColorType, ImageHeader};
+    use std::str::FromStr;
+
+    fn fctl(sequence_number: u32, delay_num: u16, delay_den: u16) -> Chunk {
+        KnownChunk::Fctl(FrameControl {
+            sequence_number,
+            width: 2,
+            height: 2,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num,
+            delay_den,
+            dispose_op: 0,
+            blend_op: 0,
+        })
+        .to_chunk()
+    }
+
+    fn raw(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn fdat(sequence_number: u32) -> Chunk {
+        KnownChunk::Fdat {
+            sequence_number,
+            data: vec![1, 2, 3],
+        }
+        .to_chunk()
+    }
+
+    fn testing_apng(frames: u32) -> Png {
+        let header = KnownChunk::Ihdr(ImageHeader {
+            width: 2,
+            height: 2,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        });
+        let actl = KnownChunk::Actl {
+            num_frames: frames,
+            num_plays: 0,
+        };
+
+        let mut chunks = vec![header.to_chunk(), actl.to_chunk(), fctl(0, 1, 25)];
+        chunks.push(raw("IDAT", &[0]));
+        for frame in 1..frames {
+            chunks.push(fctl(frame * 2 - 1, 4, 100));
+            chunks.push(fdat(frame * 2));
+        }
+        chunks.push(raw("IEND", &[]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_parse_animation() {
+        let png = testing_apng(3);
+        let animation = Animation::from_png(&png).unwrap().unwrap();
+        assert_eq!(animation.frames.len(), 3);
+        assert!(animation.frames[0].is_default_image);
+        assert!(!animation.frames[1].is_default_image);
+        assert!(animation.validate(&png).is_ok());
+    }
+
+    #[test]
+    fn test_static_png_has_no_animation() {
+        let png = Png::from_chunks(vec![raw("IHDR", &[0; 13]), raw("IEND", &[])]);
+        assert!(Animation::from_png(&png).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_out_of_order_sequence_numbers() {
+        let mut png = testing_apng(3);
+        png.chunks_mut()[6] = fdat(7);
+        let animation = Animation::from_png(&png).unwrap().unwrap();
+        assert!(animation.validate(&png).is_err());
+    }
+
+    #[test]
+    fn test_frame_count_mismatch() {
+        let mut png = testing_apng(3);
+        png.chunks_mut()[1] = KnownChunk::Actl {
+            num_frames: 5,
+            num_plays: 0,
+        }
+        .to_chunk();
+        let animation = Animation::from_png(&png).unwrap().unwrap();
+        assert!(animation.validate(&png).is_err());
+    }
+
+    #[test]
+    fn test_frame_timing_round_trip() {
+        let mut png = testing_apng(10);
+        let delays: Vec<f64> = frame_controls(&png)
+            .unwrap()
+            .iter()
+            .map(|(_, fctl)| fctl.delay_seconds())
+            .collect();
+
+        embed_in_frame_timing(&mut png, b"apng!").unwrap();
+
+        let new_delays: Vec<f64> = frame_controls(&png)
+            .unwrap()
+            .iter()
+            .map(|(_, fctl)| fctl.delay_seconds())
+            .collect();
+        assert_eq!(delays, new_delays);
+        assert_eq!(extract_from_frame_timing(&png).unwrap(), b"apng!");
+        assert!(Animation::from_png(&png)
+            .unwrap()
+            .unwrap()
+            .validate(&png)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_frame_timing_capacity_exceeded() {
+        let mut png = testing_apng(2);
+        assert!(embed_in_frame_timing(&mut png, &[0; 64]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+pub fn bits_of(data: &[u8]) -> Vec<bool> {
+    data.iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1 == 1))
+        .collect()
+}
+
+pub fn bytes_from_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8) << (8 - byte.len()))
+        .collect()
+}
+
+pub fn bits_of_value(value: u32, width: u32) -> impl Iterator<Item = bool> {
+    (0..width).rev().map(move |shift| (value >> shift) & 1 == 1)
+}
+
+pub fn value_from_bits(bits: &[bool]) -> u32 {
+    bits.iter().fold(0, |acc, &bit| (acc << 1) | bit as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bits_round_trip() {
+        let data = b"hidepng";
+        assert_eq!(bytes_from_bits(&bits_of(data)), data);
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let bits: Vec<bool> = bits_of_value(0b1011, 6).collect();
+        assert_eq!(bits, [false, false, true, false, true, true]);
+        assert_eq!(value_from_bits(&bits), 0b1011);
+    }
+}
@@ -10,7 +10,7 @@ use std::{
 use crate::chunk_type::ChunkType;
 use crate::errors;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
@@ -36,7 +36,7 @@ impl Chunk {
         }
     }
 
-    pub fn calculate_crc(chunk_type: &ChunkType, chunk_data: &Vec<u8>) -> u32 {
+    pub fn calculate_crc(chunk_type: &ChunkType, chunk_data: &[u8]) -> u32 {
         let crc_input: Vec<u8> = chunk_type
             .bytes()
             .iter()
@@ -72,7 +72,7 @@ impl Chunk {
         self.length()
             .to_be_bytes()
             .into_iter()
-            .chain(self.chunk_type().bytes().into_iter())
+            .chain(self.chunk_type().bytes())
             .chain(self.data().iter().cloned())
             .chain(self.crc().to_be_bytes())
             .collect()
@@ -94,10 +94,13 @@ impl TryFrom<&[u8]> for Chunk {
         let chunk_type_bytes: [u8; 4] = chunk_type_bytes.try_into()?;
         let chunk_type = ChunkType::try_from(chunk_type_bytes)?;
 
+        if rest.len() < length as usize + 4 {
+            return Err(errors::Error::InputTooSmall.into());
+        }
         let (data_bytes, crc_bytes) = rest.split_at(length as usize);
         let chunk_data = data_bytes.to_vec();
 
-        let crc = u32::from_be_bytes(crc_bytes.get(..4).unwrap().try_into()?);
+        let crc = u32::from_be_bytes(crc_bytes[..4].try_into()?);
 
         // validate crc
         let calculated_crc = Self::calculate_crc(&chunk_type, &chunk_data);
@@ -11,17 +11,18 @@ impl ChunkType {
         self.bytes
     }
 
+    #[allow(dead_code, reason = "parsing already rejects invalid types, kept for the tests")]
     pub fn is_valid(&self) -> bool {
         let within_valid_range = self
             .bytes
             .iter()
-            .all(|&byte| ((byte >= b'a' && byte <= b'z') || (byte >= b'A' && byte <= b'Z')));
+            .all(|&byte| byte.is_ascii_alphabetic());
 
         if !within_valid_range {
             return false;
         }
 
-        true && self.is_reserved_bit_valid()
+        self.is_reserved_bit_valid()
     }
 
     pub fn is_critical(&self) -> bool {
@@ -32,6 +33,7 @@ impl ChunkType {
         self.bytes[1].is_ascii_uppercase()
     }
 
+    #[allow(dead_code, reason = "only used by is_valid")]
     pub fn is_reserved_bit_valid(&self) -> bool {
         self.bytes[2].is_ascii_uppercase()
     }
@@ -47,7 +49,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
         let within_valid_range = value
             .iter()
-            .all(|&byte| ((byte >= b'a' && byte <= b'z') || (byte >= b'A' && byte <= b'Z')));
+            .all(|&byte| byte.is_ascii_alphabetic());
 
         if !within_valid_range {
             return Err(Error::InvalidCharacterSet(String::from_utf8(value.to_vec()).unwrap()));
@@ -74,7 +76,7 @@ impl FromStr for ChunkType {
 
         let within_valid_range = s
             .bytes()
-            .all(|byte| ((byte >= b'a' && byte <= b'z') || (byte >= b'A' && byte <= b'Z')));
+            .all(|byte| byte.is_ascii_alphabetic());
 
         if !within_valid_range {
             return Err(Error::InvalidCharacterSet(s.to_string()));
@@ -175,6 +177,7 @@ mod tests {
 
     #[ignore]
     #[test]
+    #[allow(unused_parens, clippy::manual_range_contains, reason = "spells out the valid ranges")]
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
         let within_valid_range = "Rust"
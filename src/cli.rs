@@ -1,16 +1,30 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, process::ExitCode};
 
 use clap::{Parser, Subcommand};
 
-use crate::{chunk_type::ChunkType, commands};
-
-use std::str::FromStr;
+use crate::{
+    chunk_type::ChunkType,
+    commands::{self, DecodeOptions, EncodeOptions, KeyKind, Method},
+    config::Config,
+    dump::DumpOptions,
+    lsb::LsbOptions,
+    output::{self, Format, Notes},
+    png::{Placement, VerifyCrc},
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text, help = "Print results as text or as versioned JSON.")]
+    format: Format,
+    #[arg(
+        long,
+        global = true,
+        help = "Config file profile to take defaults from."
+    )]
+    profile: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -18,52 +32,731 @@ enum Commands {
     Encode {
         #[arg(short, long, help = "Input file path.")]
         file_path: PathBuf,
-        #[arg(short, long, default_value_t = ChunkType::from_str("STXT").unwrap(),  help = "Key to store message as.")]
-        chunk_type: ChunkType,
+        #[arg(short, long, help = "Key to store message as, STXT unless configured.")]
+        chunk_type: Option<ChunkType>,
         #[arg(short, long, help = "Message to be stored.")]
         msg: String,
         #[arg(short, long, help = "Output file.")]
         output_file: Option<PathBuf>,
+        #[arg(
+            long,
+            value_enum,
+            help = "Where to hide the message, chunk unless configured."
+        )]
+        method: Option<Method>,
+        #[arg(long, value_enum, help = "Where the chunk goes with --method chunk.")]
+        placement: Option<Placement>,
+        #[arg(long, help = "Ed25519 signing key to sign the message with.")]
+        sign_key: Option<PathBuf>,
+        #[arg(long, help = "Bind the signature to the image's critical chunks.")]
+        bind_critical_chunks: bool,
+        #[arg(
+            long,
+            help = "Store a digest of the decoded image so later pixel changes can be detected."
+        )]
+        bind_image: bool,
+        #[arg(
+            long = "recipient",
+            help = "X25519 public key to encrypt the message for, can be repeated."
+        )]
+        recipients: Vec<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with = "recipients",
+            help = "Passphrase to encrypt the message with."
+        )]
+        passphrase: Option<String>,
+        #[arg(
+            long = "decoy-message",
+            requires = "passphrase",
+            help = "Decoy message revealed by the matching --decoy-passphrase, can be repeated."
+        )]
+        decoy_messages: Vec<String>,
+        #[arg(
+            long = "decoy-passphrase",
+            requires = "passphrase",
+            help = "Passphrase for the decoy message at the same position."
+        )]
+        decoy_passphrases: Vec<String>,
+        #[command(flatten)]
+        lsb: LsbOptions,
+        #[arg(
+            long,
+            help = "Compress the message before encrypting and embedding it."
+        )]
+        compress: bool,
+        #[arg(
+            long,
+            value_name = "BYTES",
+            help = "Split the message across chunks of at most this many bytes."
+        )]
+        fragment_size: Option<usize>,
+        #[arg(
+            long,
+            help = "Name to store the message under, so one chunk type can hold several."
+        )]
+        name: Option<String>,
+        #[arg(long, help = "Report what would be embedded without writing the file.")]
+        dry_run: bool,
+        #[arg(
+            long,
+            help = "Recompress the image data and drop redundant chunks afterwards."
+        )]
+        optimize: bool,
     },
 
     Decode {
         #[arg(short, long, help = "Input file path.")]
         file_path: PathBuf,
-        #[arg(short, long, default_value_t = ChunkType::from_str("STXT").unwrap(), help = "Key to store message as.")]
-        chunk_type: ChunkType,
+        #[arg(short, long, help = "Key to store message as, STXT unless configured.")]
+        chunk_type: Option<ChunkType>,
+        #[arg(
+            long,
+            value_enum,
+            help = "Where the message is hidden, chunk unless configured."
+        )]
+        method: Option<Method>,
+        #[arg(long, help = "Ed25519 public key the message must be signed with.")]
+        verify_key: Option<PathBuf>,
+        #[arg(long, help = "X25519 private key to decrypt the message with.")]
+        identity: Option<PathBuf>,
+        #[arg(long, help = "Passphrase the message was encrypted with.")]
+        passphrase: Option<String>,
+        #[arg(
+            long,
+            help = "Key the message was scattered with by lsb or palette methods."
+        )]
+        key: Option<String>,
+        #[arg(long, value_enum, default_value_t = VerifyCrc::Error, help = "How to treat bad CRCs; skip and warn also read past damaged bytes.")]
+        verify_crc: VerifyCrc,
+        #[arg(long, help = "Only read the message stored under this name.")]
+        name: Option<String>,
     },
 
     Remove {
         #[arg(short, long, help = "Input file path.")]
         file_path: PathBuf,
-        #[arg(short, long, default_value_t = ChunkType::from_str("STXT").unwrap(), help = "Key to store message as.")]
+        #[arg(short, long, help = "Key to store message as, STXT unless configured.")]
+        chunk_type: Option<ChunkType>,
+        #[arg(long, help = "Only remove the message stored under this name.")]
+        name: Option<String>,
+        #[arg(
+            long,
+            requires = "name",
+            help = "Passphrase to find a named message sealed with it; its whole container is removed."
+        )]
+        passphrase: Option<String>,
+    },
+
+    List {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(
+            short,
+            long,
+            help = "Key the messages are stored as, STXT unless configured."
+        )]
+        chunk_type: Option<ChunkType>,
+        #[arg(long, help = "Passphrase to open messages sealed with it.")]
+        passphrase: Option<String>,
+    },
+
+    Update {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(
+            short,
+            long,
+            help = "Key the message is stored as, STXT unless configured."
+        )]
+        chunk_type: Option<ChunkType>,
+        #[arg(long, help = "Name of the message to replace.")]
+        name: String,
+        #[arg(short, long, help = "New message to store.")]
+        msg: String,
+        #[arg(short, long, help = "Output file.")]
+        output_file: Option<PathBuf>,
+        #[arg(
+            long,
+            value_enum,
+            help = "Where the new chunks go, in place of the old ones when left out."
+        )]
+        placement: Option<Placement>,
+        #[arg(long, help = "Ed25519 signing key to sign the message with.")]
+        sign_key: Option<PathBuf>,
+        #[arg(long, help = "Bind the signature to the image's critical chunks.")]
+        bind_critical_chunks: bool,
+        #[arg(
+            long,
+            help = "Store a digest of the decoded image so later pixel changes can be detected."
+        )]
+        bind_image: bool,
+        #[arg(
+            long = "recipient",
+            help = "X25519 public key to encrypt the message for, can be repeated."
+        )]
+        recipients: Vec<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with = "recipients",
+            help = "Passphrase the message is sealed with, used to find it and seal the new one; other slots are kept only if given again as decoys."
+        )]
+        passphrase: Option<String>,
+        #[arg(
+            long = "decoy-message",
+            requires = "passphrase",
+            help = "Decoy message revealed by the matching --decoy-passphrase, can be repeated."
+        )]
+        decoy_messages: Vec<String>,
+        #[arg(
+            long = "decoy-passphrase",
+            requires = "passphrase",
+            help = "Passphrase for the decoy message at the same position."
+        )]
+        decoy_passphrases: Vec<String>,
+        #[arg(
+            long,
+            help = "Compress the message before encrypting and embedding it."
+        )]
+        compress: bool,
+        #[arg(
+            long,
+            value_name = "BYTES",
+            help = "Split the message across chunks of at most this many bytes."
+        )]
+        fragment_size: Option<usize>,
+    },
+
+    ExtractChunk {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Type of the chunk to copy out.")]
         chunk_type: ChunkType,
+        #[arg(
+            long,
+            default_value_t = 0,
+            help = "Which chunk of that type, counting from 0."
+        )]
+        index: usize,
+        #[arg(short, long, help = "File to write the chunk to.")]
+        output_file: PathBuf,
+        #[arg(long, help = "Write the whole chunk with its length, type and CRC.")]
+        whole: bool,
+    },
+
+    InsertChunk {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(
+            long,
+            help = "File holding the chunk's data, or a whole chunk when no type is given."
+        )]
+        chunk_file: PathBuf,
+        #[arg(
+            short,
+            long,
+            help = "Type of the chunk, read from the chunk file when left out."
+        )]
+        chunk_type: Option<ChunkType>,
+        #[arg(short, long, help = "Output file.")]
+        output_file: Option<PathBuf>,
+        #[arg(long, value_enum, help = "Where the chunk goes.")]
+        placement: Option<Placement>,
+    },
+
+    Dump {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Only dump chunks of this type.")]
+        chunk_type: Option<ChunkType>,
+        #[arg(
+            long,
+            requires = "chunk_type",
+            help = "Only dump this chunk of the type, counting from 0."
+        )]
+        index: Option<usize>,
+        #[arg(long, help = "Show at most this many bytes of each chunk's data.")]
+        max_bytes: Option<usize>,
+        #[arg(long, help = "Describe known chunks and hidepng payload headers.")]
+        annotate: bool,
+        #[arg(long, value_enum, default_value_t = VerifyCrc::Error, help = "How to treat bad CRCs; skip and warn also read past damaged bytes.")]
+        verify_crc: VerifyCrc,
+    },
+
+    Diff {
+        #[arg(help = "First file.")]
+        a: PathBuf,
+        #[arg(help = "Second file, compared against the first.")]
+        b: PathBuf,
+    },
+
+    Transplant {
+        #[arg(short, long, help = "Input file path, the image to copy chunks into.")]
+        file_path: PathBuf,
+        #[arg(long, help = "Image to copy ancillary chunks from.")]
+        source: PathBuf,
+        #[arg(short, long, help = "Output file.")]
+        output_file: Option<PathBuf>,
+        #[arg(
+            short,
+            long = "chunk-type",
+            help = "Only copy chunks of this type, can be repeated."
+        )]
+        chunk_types: Vec<ChunkType>,
+        #[arg(long, help = "Also copy chunks that aren't marked safe to copy.")]
+        force: bool,
+    },
+
+    Optimize {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Output file.")]
+        output_file: Option<PathBuf>,
+    },
+
+    Inspect {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(long, value_enum, default_value_t = VerifyCrc::Error, help = "How to treat bad CRCs; skip and warn also read past damaged bytes.")]
+        verify_crc: VerifyCrc,
+    },
+
+    Repair {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Output file.")]
+        output_file: Option<PathBuf>,
+    },
+
+    Verify {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Key to store message as, STXT unless configured.")]
+        chunk_type: Option<ChunkType>,
+        #[arg(
+            long,
+            value_enum,
+            help = "Where the message is hidden, chunk unless configured."
+        )]
+        method: Option<Method>,
+        #[arg(long, help = "Ed25519 public key the message must be signed with.")]
+        verify_key: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Key the message was scattered with by lsb or palette methods."
+        )]
+        key: Option<String>,
+    },
+
+    Capacity {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Key to store message as, STXT unless configured.")]
+        chunk_type: Option<ChunkType>,
+        #[arg(long, help = "Ed25519 signing key the message would be signed with.")]
+        sign_key: Option<PathBuf>,
+        #[arg(long, help = "Count a binding to the image's critical chunks.")]
+        bind_critical_chunks: bool,
+        #[arg(long, help = "Count a digest of the decoded image.")]
+        bind_image: bool,
+        #[arg(
+            long = "recipient",
+            help = "X25519 public key the message would be encrypted for, can be repeated."
+        )]
+        recipients: Vec<PathBuf>,
+        #[arg(
+            long,
+            conflicts_with = "recipients",
+            help = "Passphrase the message would be encrypted with."
+        )]
+        passphrase: Option<String>,
+        #[arg(
+            long = "decoy-message",
+            requires = "passphrase",
+            help = "Decoy message that has to fit alongside, can be repeated."
+        )]
+        decoy_messages: Vec<String>,
+        #[command(flatten)]
+        lsb: LsbOptions,
+    },
+
+    Manifest {
+        #[command(subcommand)]
+        action: ManifestAction,
+    },
+
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    Keygen {
+        #[arg(
+            short,
+            long,
+            help = "Path of the private key, the public key is written next to it."
+        )]
+        output_file: PathBuf,
+        #[arg(long, value_enum, default_value_t = KeyKind::Signing, help = "Kind of key pair to generate.")]
+        kind: KeyKind,
+        #[arg(long, help = "Overwrite existing key files.")]
+        force: bool,
     },
 }
 
-pub fn parse() -> anyhow::Result<()> {
+#[derive(Subcommand, Debug)]
+enum ManifestAction {
+    #[command(about = "Write a PNG's chunks to an editable JSON manifest.")]
+    Export {
+        #[arg(short, long, help = "Input file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Manifest file to write, as JSON.")]
+        output_file: PathBuf,
+        #[arg(
+            long,
+            help = "Directory, relative to the manifest and inside its directory, to write large binary chunks to."
+        )]
+        data_dir: Option<PathBuf>,
+    },
+    #[command(about = "Build a PNG from a manifest, filling in lengths and CRCs.")]
+    Build {
+        #[arg(short, long, help = "JSON manifest file path.")]
+        file_path: PathBuf,
+        #[arg(short, long, help = "Output file.")]
+        output_file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    #[command(about = "Show the settings commands run with when no flags are given.")]
+    Show,
+}
+
+impl Commands {
+    fn name(&self) -> &'static str {
+        match self {
+            Commands::Encode { .. } => "encode",
+            Commands::Decode { .. } => "decode",
+            Commands::Remove { .. } => "remove",
+            Commands::List { .. } => "list",
+            Commands::Update { .. } => "update",
+            Commands::ExtractChunk { .. } => "extract-chunk",
+            Commands::InsertChunk { .. } => "insert-chunk",
+            Commands::Dump { .. } => "dump",
+            Commands::Diff { .. } => "diff",
+            Commands::Transplant { .. } => "transplant",
+            Commands::Optimize { .. } => "optimize",
+            Commands::Inspect { .. } => "inspect",
+            Commands::Repair { .. } => "repair",
+            Commands::Verify { .. } => "verify",
+            Commands::Capacity { .. } => "capacity",
+            Commands::Manifest { .. } => "manifest",
+            Commands::Config { .. } => "config",
+            Commands::Keygen { .. } => "keygen",
+        }
+    }
+}
+
+pub fn parse() -> ExitCode {
     let args = Cli::parse();
+    let name = args.command.name();
 
-    match args.command {
+    match run(args.command, args.format, args.profile.as_deref()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            match args.format {
+                Format::Text => eprintln!("Error: {:?}", err),
+                Format::Json => output::print_error(name, &err),
+            }
+            ExitCode::from(output::exit_code(&err))
+        }
+    }
+}
+
+fn run(command: Commands, format: Format, profile: Option<&str>) -> anyhow::Result<()> {
+    let name = command.name();
+    let config = Config::load(profile)?;
+
+    match command {
         Commands::Encode {
             file_path,
             chunk_type,
             msg,
             output_file,
-        } => commands::encode(file_path, chunk_type, msg, output_file)?,
+            method,
+            placement,
+            sign_key,
+            bind_critical_chunks,
+            bind_image,
+            recipients,
+            passphrase,
+            decoy_messages,
+            decoy_passphrases,
+            lsb,
+            compress,
+            fragment_size,
+            name: message_name,
+            dry_run,
+            optimize,
+        } => {
+            let report = commands::encode(
+                file_path,
+                config.chunk_type(chunk_type),
+                msg,
+                output_file,
+                config.method(method),
+                EncodeOptions {
+                    sign_key: sign_key.or(config.settings.sign_key.clone()),
+                    bind_critical_chunks,
+                    bind_image,
+                    recipients: config.recipients(recipients, passphrase.is_some()),
+                    passphrase,
+                    decoy_messages,
+                    decoy_passphrases,
+                    lsb,
+                    placement: config.placement(placement),
+                    compress,
+                    fragment_size,
+                    name: message_name,
+                    dry_run,
+                    optimize,
+                },
+            )?;
+            output::print(format, name, &Notes::from(report))?;
+        }
         Commands::Decode {
             file_path,
             chunk_type,
+            method,
+            verify_key,
+            identity,
+            passphrase,
+            key,
+            verify_crc,
+            name: message_name,
         } => {
-            let data = commands::decode(file_path, chunk_type)?;
-            for msg in data {
-                println!("{}", msg);
-            }
+            let data = commands::decode(
+                file_path,
+                config.chunk_type(chunk_type),
+                config.method(method),
+                DecodeOptions {
+                    verify_key: verify_key.or(config.settings.verify_key.clone()),
+                    identity: identity.or(config.settings.identity.clone()),
+                    passphrase,
+                    key,
+                    verify_crc,
+                    name: message_name,
+                },
+            )?;
+            output::print(format, name, &data)?;
         }
         Commands::Remove {
             file_path,
             chunk_type,
-        } => commands::remove(file_path, chunk_type)?,
+            name: message_name,
+            passphrase,
+        } => {
+            let notes = commands::remove(
+                file_path,
+                config.chunk_type(chunk_type),
+                message_name,
+                passphrase,
+            )?;
+            output::print(format, name, &notes)?;
+        }
+        Commands::List {
+            file_path,
+            chunk_type,
+            passphrase,
+        } => {
+            let listing = commands::list(file_path, config.chunk_type(chunk_type), passphrase)?;
+            output::print(format, name, &listing)?;
+        }
+        Commands::Update {
+            file_path,
+            chunk_type,
+            name: message_name,
+            msg,
+            output_file,
+            placement,
+            sign_key,
+            bind_critical_chunks,
+            bind_image,
+            recipients,
+            passphrase,
+            decoy_messages,
+            decoy_passphrases,
+            compress,
+            fragment_size,
+        } => {
+            let notes = commands::update(
+                file_path,
+                config.chunk_type(chunk_type),
+                msg,
+                output_file,
+                placement,
+                EncodeOptions {
+                    sign_key: sign_key.or(config.settings.sign_key.clone()),
+                    bind_critical_chunks,
+                    bind_image,
+                    recipients: config.recipients(recipients, passphrase.is_some()),
+                    passphrase,
+                    decoy_messages,
+                    decoy_passphrases,
+                    compress,
+                    fragment_size,
+                    name: Some(message_name),
+                    ..Default::default()
+                },
+            )?;
+            output::print(format, name, &notes)?;
+        }
+        Commands::ExtractChunk {
+            file_path,
+            chunk_type,
+            index,
+            output_file,
+            whole,
+        } => {
+            let report = commands::extract_chunk(file_path, chunk_type, index, output_file, whole)?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::InsertChunk {
+            file_path,
+            chunk_file,
+            chunk_type,
+            output_file,
+            placement,
+        } => {
+            let report = commands::insert_chunk(
+                file_path,
+                chunk_file,
+                chunk_type,
+                output_file,
+                config.placement(placement),
+            )?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Dump {
+            file_path,
+            chunk_type,
+            index,
+            max_bytes,
+            annotate,
+            verify_crc,
+        } => {
+            let options = DumpOptions {
+                chunk_type,
+                index,
+                max_bytes,
+                annotate,
+            };
+            output::print(
+                format,
+                name,
+                &commands::dump(file_path, verify_crc, options)?,
+            )?;
+        }
+        Commands::Diff { a, b } => output::print(format, name, &commands::diff(a, b)?)?,
+        Commands::Transplant {
+            file_path,
+            source,
+            output_file,
+            chunk_types,
+            force,
+        } => {
+            let report = commands::transplant(file_path, source, output_file, chunk_types, force)?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Optimize {
+            file_path,
+            output_file,
+        } => {
+            let report = commands::optimize(file_path, output_file)?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Inspect {
+            file_path,
+            verify_crc,
+        } => {
+            output::print(format, name, &commands::inspect(file_path, verify_crc)?)?;
+        }
+        Commands::Repair {
+            file_path,
+            output_file,
+        } => {
+            let report = commands::repair(file_path, output_file)?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Verify {
+            file_path,
+            chunk_type,
+            method,
+            verify_key,
+            key,
+        } => {
+            let report = commands::verify(
+                file_path,
+                config.chunk_type(chunk_type),
+                config.method(method),
+                verify_key.or(config.settings.verify_key.clone()),
+                key,
+            )?;
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Capacity {
+            file_path,
+            chunk_type,
+            sign_key,
+            bind_critical_chunks,
+            bind_image,
+            recipients,
+            passphrase,
+            decoy_messages,
+            lsb,
+        } => {
+            let options = EncodeOptions {
+                sign_key: sign_key.or(config.settings.sign_key.clone()),
+                bind_critical_chunks,
+                bind_image,
+                recipients: config.recipients(recipients, passphrase.is_some()),
+                passphrase,
+                decoy_messages,
+                lsb,
+                ..Default::default()
+            };
+            output::print(
+                format,
+                name,
+                &commands::capacity(file_path, config.chunk_type(chunk_type), options)?,
+            )?;
+        }
+        Commands::Manifest { action } => {
+            let report = match action {
+                ManifestAction::Export {
+                    file_path,
+                    output_file,
+                    data_dir,
+                } => commands::export_manifest(file_path, output_file, data_dir)?,
+                ManifestAction::Build {
+                    file_path,
+                    output_file,
+                } => commands::build_manifest(file_path, output_file)?,
+            };
+            output::print(format, name, &Notes::from(report))?;
+        }
+        Commands::Config {
+            action: ConfigAction::Show,
+        } => output::print(format, name, &config.effective())?,
+        Commands::Keygen {
+            output_file,
+            kind,
+            force,
+        } => {
+            let report = vec![commands::keygen(output_file, kind, force)?];
+            output::print(format, name, &Notes::from(report))?;
+        }
     };
 
     Ok(())
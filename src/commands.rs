@@ -1,49 +1,1253 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use crate::{chunk::Chunk, chunk_type::ChunkType, errors::Error, png::Png};
+use clap::ValueEnum;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use x25519_dalek::StaticSecret;
+
+use crate::{
+    apng::{self, Animation},
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    deniable,
+    diff::{self, Diff},
+    dump::{self, Dump, DumpOptions},
+    encryption,
+    entries::{self, Listing},
+    errors::Error,
+    image::{Fingerprint, RawImage},
+    keys,
+    known_chunk::{ColorType, KnownChunk},
+    lsb::{self, LsbOptions},
+    manifest::{self, Manifest},
+    optimize,
+    output::{ErrorObject, Notes, Report},
+    palette,
+    payload::{self, ContentType, Payload},
+    png::{ParseOptions, Placement, Png, VerifyCrc},
+    signing, transplant,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    Chunk,
+    FrameTiming,
+    Lsb,
+    Palette,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum KeyKind {
+    Signing,
+    Encryption,
+}
+
+#[derive(Debug, Default)]
+pub struct EncodeOptions {
+    pub sign_key: Option<PathBuf>,
+    pub bind_critical_chunks: bool,
+    pub bind_image: bool,
+    pub recipients: Vec<PathBuf>,
+    pub passphrase: Option<String>,
+    pub decoy_messages: Vec<String>,
+    pub decoy_passphrases: Vec<String>,
+    pub lsb: LsbOptions,
+    pub placement: Placement,
+    pub compress: bool,
+    pub fragment_size: Option<usize>,
+    pub name: Option<String>,
+    pub dry_run: bool,
+    pub optimize: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct DecodeOptions {
+    pub verify_key: Option<PathBuf>,
+    pub identity: Option<PathBuf>,
+    pub passphrase: Option<String>,
+    pub key: Option<String>,
+    pub verify_crc: VerifyCrc,
+    pub name: Option<String>,
+}
 
 pub fn encode(
     file_path: PathBuf,
     chunk_type: ChunkType,
     msg: String,
     output_file: Option<PathBuf>,
-) -> anyhow::Result<()> {
+    method: Method,
+    options: EncodeOptions,
+) -> anyhow::Result<Vec<String>> {
     let contents = fs::read(&file_path)?;
-    let msg = msg.as_bytes().to_vec();
-    let chunk = Chunk::new(chunk_type, msg);
+    let mut png = Png::try_from(contents.as_slice())?;
+
+    check_binding(method, &options)?;
+    if options.fragment_size.is_some() && method != Method::Chunk {
+        return Err(
+            Error::IncompatibleOptions("--fragment-size only applies to --method chunk").into(),
+        );
+    }
+    if let Some(name) = &options.name {
+        entries::check_name(name)?;
+        if method == Method::Chunk && png.chunk_by_type(&chunk_type.to_string()).is_some() {
+            let existing = entries::entries(&png, &chunk_type, options.passphrase.as_deref())?;
+            if entries::find(&existing, name).is_ok() {
+                return Err(Error::DuplicateMessageName(name.clone()).into());
+            }
+        }
+    }
+    // the critical chunk digest covers IDAT, so unless the pixels are about to
+    // be rewritten anyway, optimize before the message is framed and signed
+    let mut optimization = None;
+    if options.optimize && !changes_pixels(method) {
+        optimization = Some(optimize::optimize(&mut png, Some(&chunk_type))?);
+    }
+    let msg = frame_message(&png, &chunk_type, msg.into_bytes(), &options)?;
+    let msg = seal_message(msg, &options)?;
+
+    let mut preservation = None;
+    let report = match method {
+        Method::Chunk => match options.fragment_size {
+            Some(size) => insert_fragments(&mut png, &chunk_type, &msg, size, options.placement)?,
+            None => insert_chunk_at(
+                &mut png,
+                Chunk::new(chunk_type.clone(), msg),
+                options.placement,
+            ),
+        },
+        Method::FrameTiming => {
+            let animation = Animation::from_png(&png)?.ok_or(Error::InvalidAnimation(
+                "image is not an animated PNG".to_string(),
+            ))?;
+            animation.validate(&png)?;
+            let report = format!(
+                "{} of {} bytes of frame timing capacity in the fcTL delays of {} frames",
+                msg.len(),
+                apng::timing_capacity(&png)?,
+                animation.num_frames
+            );
+            apng::embed_in_frame_timing(&mut png, &msg)?;
+            report
+        }
+        Method::Lsb => {
+            let mut image = RawImage::from_png(&png)?;
+            let plan = lsb::embed(&mut image, &msg, &options.lsb)?;
+            preservation = Some(image.write_to(&mut png)?);
+            format!("IDAT pixels, {}", plan)
+        }
+        Method::Palette => {
+            let (plan, preserved) = palette::embed(&mut png, &msg, &options.lsb)?;
+            preservation = Some(preserved);
+            format!("IDAT palette indices with PLTE reordered, {}", plan)
+        }
+    };
 
+    let mut lines = Vec::new();
+    if options.dry_run {
+        lines.push(format!("dry run, nothing written: {}", report));
+    }
+    if let Some(preservation) = preservation {
+        lines.push(format!("compression fingerprint: {}", preservation));
+    }
+    if options.optimize && changes_pixels(method) {
+        optimization = Some(optimize::optimize(&mut png, Some(&chunk_type))?);
+    }
+    if let Some(optimization) = optimization {
+        lines.push(format!("optimized: {}", optimization));
+    }
+
+    let bytes = png.as_bytes();
+    if options.dry_run || options.optimize {
+        lines.push(size_change(contents.len(), bytes.len()));
+    }
+    if !options.dry_run {
+        fs::write(output_file.unwrap_or(file_path), bytes)?;
+    }
+    Ok(lines)
+}
+
+pub fn optimize(file_path: PathBuf, output_file: Option<PathBuf>) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(&file_path)?;
     let mut png = Png::try_from(contents.as_slice())?;
-    png.insert_between(chunk);
-    let output = png.as_bytes();
 
-    fs::write(output_file.unwrap_or(file_path), output)?;
-    Ok(())
+    let optimization = optimize::optimize(&mut png, None)?;
+    let bytes = png.as_bytes();
+    let lines = vec![
+        format!("optimized: {}", optimization),
+        size_change(contents.len(), bytes.len()),
+    ];
+    fs::write(output_file.unwrap_or(file_path), bytes)?;
+    Ok(lines)
+}
+
+fn insert_chunk_at(png: &mut Png, chunk: Chunk, placement: Placement) -> String {
+    let report = format!("{} chunk of {} bytes", chunk.chunk_type(), chunk.length());
+    let index = png.insert_at(chunk, placement);
+    format!(
+        "{} inserted as chunk {}, after {}",
+        report,
+        index,
+        png.chunks()[index - 1].chunk_type()
+    )
+}
+
+// Fragments go next to each other, in order, where a single chunk would.
+fn insert_fragments(
+    png: &mut Png,
+    chunk_type: &ChunkType,
+    msg: &[u8],
+    size: usize,
+    placement: Placement,
+) -> Result<String, Error> {
+    let mut fragments = message_chunks(chunk_type, msg.to_vec(), Some(size))?.into_iter();
+    let count = fragments.len();
+    let index = png.insert_at(fragments.next().unwrap(), placement);
+    let mut chunks = png.chunks().to_vec();
+    chunks.splice(index + 1..index + 1, fragments);
+    *png = Png::from_chunks(chunks);
+
+    Ok(format!(
+        "{} bytes in {} {} chunks of up to {} bytes inserted as chunks {} to {}, after {}",
+        msg.len(),
+        count,
+        chunk_type,
+        size,
+        index,
+        index + count - 1,
+        png.chunks()[index - 1].chunk_type()
+    ))
+}
+
+fn message_chunks(
+    chunk_type: &ChunkType,
+    msg: Vec<u8>,
+    fragment_size: Option<usize>,
+) -> Result<Vec<Chunk>, Error> {
+    let pieces = match fragment_size {
+        Some(size) => Payload::fragment(&msg, size)?,
+        None => vec![msg],
+    };
+    Ok(pieces
+        .into_iter()
+        .map(|data| Chunk::new(chunk_type.clone(), data))
+        .collect())
+}
+
+fn size_change(before: usize, after: usize) -> String {
+    format!(
+        "file size {} -> {} bytes ({:+})",
+        before,
+        after,
+        after as i64 - before as i64
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct Capacity {
+    pub overhead: usize,
+    // size of the smallest passphrase container, when there is a passphrase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<usize>,
+    pub methods: Vec<MethodCapacity>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MethodCapacity {
+    pub method: Method,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bits_per_sample: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+}
+
+impl Report for Capacity {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "overhead: {} bytes of payload framing{}",
+            self.overhead,
+            match self.container {
+                Some(size) => format!(
+                    ", then a {} slot passphrase container of at least {} bytes",
+                    deniable::SLOTS,
+                    size
+                ),
+                None => String::new(),
+            }
+        )];
+        for capacity in &self.methods {
+            let mut label = capacity
+                .method
+                .to_possible_value()
+                .unwrap()
+                .get_name()
+                .to_string();
+            if let Some(chunk_type) = &capacity.chunk_type {
+                label = format!("{} ({})", label, chunk_type);
+            }
+            if let (Some(channels), Some(bits)) = (&capacity.channels, capacity.bits_per_sample) {
+                label = format!("{} ({}, {} bits per sample)", label, channels, bits);
+            }
+            match (&capacity.bytes, &capacity.error) {
+                (_, Some(error)) => lines.push(format!("{}: {}", label, error.message)),
+                (bytes, None) => lines.push(format!("{}: {} bytes", label, bytes.unwrap_or(0))),
+            }
+        }
+        lines
+    }
 }
 
-pub fn decode(file_path: PathBuf, chunk_type: ChunkType) -> anyhow::Result<Vec<String>> {
+impl MethodCapacity {
+    fn new(method: Method, raw: Result<usize, Error>, fits: impl Fn(usize) -> usize) -> Self {
+        let (bytes, error) = match raw {
+            Ok(raw) => (Some(fits(raw)), None),
+            Err(err) => (None, Some(ErrorObject::from(&err))),
+        };
+        MethodCapacity {
+            method,
+            chunk_type: None,
+            channels: None,
+            bits_per_sample: None,
+            bytes,
+            error,
+        }
+    }
+}
+
+// How many message bytes each method can take once the payload framing and
+// passphrase container asked for in `options` are added.
+pub fn capacity(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    options: EncodeOptions,
+) -> anyhow::Result<Capacity> {
     let contents = fs::read(file_path)?;
     let png = Png::try_from(contents.as_slice())?;
 
-    let chunks = png
-        .chunk_by_type(&chunk_type.to_string())
-        .ok_or(Error::ChunkTypeNotFound(chunk_type.to_string()))?;
+    let overhead = frame_message(&png, &chunk_type, Vec::new(), &options)?.len();
+    let fits = |raw| message_capacity(raw, overhead, Payload::OVERHEAD, &options);
+    let mut methods = Vec::new();
+
+    methods.push(MethodCapacity {
+        chunk_type: Some(chunk_type.to_string()),
+        ..MethodCapacity::new(Method::Chunk, Ok(MAX_CHUNK_LENGTH), fits)
+    });
+    let raw = match Animation::from_png(&png)? {
+        Some(_) => apng::timing_capacity(&png),
+        None => Err(Error::InvalidAnimation(
+            "image is not an animated PNG".to_string(),
+        )),
+    };
+    methods.push(MethodCapacity::new(Method::FrameTiming, raw, fits));
+
+    let image = RawImage::from_png(&png)?;
+    for settings in lsb_settings(&image, &options.lsb) {
+        let raw = check_binding(Method::Lsb, &options)
+            .and_then(|()| lsb::plan(&image, 0, &settings))
+            .map(|plan| plan.capacity());
+        methods.push(MethodCapacity {
+            channels: Some(settings.channels.to_string()),
+            bits_per_sample: Some(settings.bits_per_sample),
+            ..MethodCapacity::new(Method::Lsb, raw, fits)
+        });
+    }
+    let raw = check_binding(Method::Palette, &options)
+        .and_then(|()| palette::plan(&png, 0, &options.lsb))
+        .map(|plan| plan.capacity());
+    methods.push(MethodCapacity::new(Method::Palette, raw, fits));
+
+    Ok(Capacity {
+        overhead,
+        container: options
+            .passphrase
+            .as_ref()
+            .map(|_| deniable::sealed_size(0)),
+        methods,
+    })
+}
+
+// PNG chunk lengths are limited to 2^31 - 1 bytes.
+const MAX_CHUNK_LENGTH: usize = i32::MAX as usize;
+
+// The requested settings first, then the common channel and bit plane choices
+// that work with the rest of them.
+fn lsb_settings(image: &RawImage, requested: &LsbOptions) -> Vec<LsbOptions> {
+    let has_alpha = matches!(
+        image.header.color_type,
+        ColorType::Rgba | ColorType::GrayscaleAlpha
+    );
+    let mut settings = vec![requested.clone()];
+    for (channels, bits_per_sample) in [("rgb", 1), ("rgb", 2), ("rgba", 1), ("rgba", 2)] {
+        let candidate = LsbOptions {
+            channels: channels.parse().unwrap(),
+            bits_per_sample,
+            ..requested.clone()
+        };
+        let redundant = settings.iter().any(|existing| {
+            existing.channels == candidate.channels
+                && existing.bits_per_sample == candidate.bits_per_sample
+        });
+        if redundant || (channels == "rgba" && !has_alpha) {
+            continue;
+        }
+        if lsb::plan(image, 0, &candidate).is_ok() {
+            settings.push(candidate);
+        }
+    }
+    settings
+}
 
-    let data: Vec<String> = chunks
+fn message_capacity(
+    raw: usize,
+    overhead: usize,
+    decoy_overhead: usize,
+    options: &EncodeOptions,
+) -> usize {
+    let raw = match &options.passphrase {
+        Some(_) => match deniable::capacity(raw) {
+            Some(sealed)
+                if options
+                    .decoy_messages
+                    .iter()
+                    .all(|decoy| decoy.len() + decoy_overhead <= sealed) =>
+            {
+                sealed
+            }
+            _ => 0,
+        },
+        None => raw,
+    };
+    raw.saturating_sub(overhead)
+}
+
+// the digests would cover pixels that embedding is about to change
+fn changes_pixels(method: Method) -> bool {
+    matches!(method, Method::Lsb | Method::Palette)
+}
+
+fn check_binding(method: Method, options: &EncodeOptions) -> Result<(), Error> {
+    if changes_pixels(method) && (options.bind_image || options.bind_critical_chunks) {
+        return Err(Error::IncompatibleOptions(
+            "--bind-image and --bind-critical-chunks can't be used with pixel methods",
+        ));
+    }
+    Ok(())
+}
+
+// Wraps the message in a payload, compressing, encrypting, binding and
+// signing it as asked.
+fn frame_message(
+    png: &Png,
+    chunk_type: &ChunkType,
+    msg: Vec<u8>,
+    options: &EncodeOptions,
+) -> anyhow::Result<Vec<u8>> {
+    if options.bind_critical_chunks && options.sign_key.is_none() {
+        return Err(
+            Error::IncompatibleOptions("--bind-critical-chunks needs a signing key").into(),
+        );
+    }
+    let mut payload = Payload::new(msg);
+    payload.name = options.name.clone();
+    if options.compress {
+        if payload.message.len() > payload::MAX_DECOMPRESSED_SIZE {
+            return Err(
+                Error::IncompatibleOptions("--compress only takes messages up to 64 MiB").into(),
+            );
+        }
+        payload.message = payload::compress(&payload.message);
+        payload.compressed = true;
+    }
+    if !options.recipients.is_empty() {
+        if options.recipients.len() > u8::MAX as usize {
+            return Err(Error::TooManyRecipients(options.recipients.len()).into());
+        }
+        let recipients = options
+            .recipients
+            .iter()
+            .map(|path| encryption::read_recipient(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (encryption, ciphertext) = encryption::encrypt(&payload.message, &recipients);
+        payload.encryption = Some(encryption);
+        payload.message = ciphertext;
+    }
+    if options.bind_critical_chunks {
+        payload.critical_digest = Some(signing::critical_chunks_digest(png, chunk_type));
+    }
+    if options.bind_image {
+        payload.image_digest = Some(signing::image_digest(png)?);
+    }
+    if let Some(sign_key) = &options.sign_key {
+        signing::sign(&mut payload, &signing::read_signing_key(sign_key)?);
+    }
+    Ok(payload.as_bytes())
+}
+
+fn seal_message(msg: Vec<u8>, options: &EncodeOptions) -> Result<Vec<u8>, Error> {
+    let Some(passphrase) = &options.passphrase else {
+        return Ok(msg);
+    };
+    if options.decoy_messages.len() != options.decoy_passphrases.len() {
+        return Err(Error::InvalidDecoys(
+            "every --decoy-message needs a --decoy-passphrase".to_string(),
+        ));
+    }
+    let decoys: Vec<Vec<u8>> = options
+        .decoy_messages
         .iter()
-        .map(|chunk| Ok(chunk.data_as_string()?))
-        .map(|chunk: anyhow::Result<String>| chunk.unwrap())
+        .map(|decoy| Payload::new(decoy.clone().into_bytes()).as_bytes())
         .collect();
+    let mut entries = vec![(msg.as_slice(), passphrase.as_str())];
+    entries.extend(
+        decoys
+            .iter()
+            .map(Vec::as_slice)
+            .zip(options.decoy_passphrases.iter().map(String::as_str)),
+    );
+    deniable::seal(&entries)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Messages {
+    pub messages: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl Report for Messages {
+    fn lines(&self) -> Vec<String> {
+        self.messages.clone()
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+}
+
+pub fn decode(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    method: Method,
+    options: DecodeOptions,
+) -> anyhow::Result<Messages> {
+    let contents = fs::read(file_path)?;
+    let png = read_png(&contents, options.verify_crc)?;
+
+    let trusted_key = match &options.verify_key {
+        Some(path) => Some(signing::read_verifying_key(path)?),
+        None => None,
+    };
+    let identity = match &options.identity {
+        Some(path) => Some(encryption::read_identity(path)?),
+        None => None,
+    };
+
+    let hidden = hidden_data(&png, &chunk_type, method, options.key.as_deref())?;
+    let mut warnings = Vec::new();
+    let mut skipped = None;
+    let messages: Vec<String> = Payload::reassemble(hidden)
+        .into_iter()
+        .filter_map(|data| {
+            // one malformed or forged payload doesn't hide the others
+            let data = match data {
+                Ok(data) => data,
+                Err(err) => {
+                    warnings.push(format!("skipped a payload that can't be read: {}", err));
+                    skipped.get_or_insert(err);
+                    return None;
+                }
+            };
+            let data = match &options.passphrase {
+                Some(passphrase) => match deniable::open(&data, passphrase) {
+                    Ok(data) => data,
+                    // another message's container when looking for a name
+                    Err(_) if options.name.is_some() => return None,
+                    Err(err) => return Some(Err(err.into())),
+                },
+                None => data,
+            };
+            // unframed data is a chunk that only shares the type, or a message
+            // from before every method framed its messages
+            if !Payload::is_payload(&data) {
+                if options.name.is_some() {
+                    return None;
+                }
+                if trusted_key.is_some() {
+                    return Some(Err(Error::UnsignedPayload.into()));
+                }
+                warnings.push(
+                    "printed data that isn't a hidepng payload, from an older version or another program"
+                        .to_string(),
+                );
+                return Some(String::from_utf8(data).map_err(Into::into));
+            }
+
+            let payload = match Payload::try_from(data.as_slice()) {
+                Ok(payload) => payload,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if options.name.is_some() && payload.name != options.name {
+                return None;
+            }
+            Some(read_payload(
+                &png,
+                &chunk_type,
+                payload,
+                trusted_key.as_ref(),
+                identity.as_ref(),
+            ))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    if messages.is_empty() {
+        return Err(match (options.name, skipped) {
+            (_, Some(err)) => err,
+            (Some(name), None) => Error::MessageNameNotFound(name),
+            (None, None) => Error::NoHiddenData,
+        }
+        .into());
+    }
+    Ok(Messages { messages, warnings })
+}
+
+fn read_payload(
+    png: &Png,
+    chunk_type: &ChunkType,
+    payload: Payload,
+    trusted_key: Option<&VerifyingKey>,
+    identity: Option<&StaticSecret>,
+) -> anyhow::Result<String> {
+    if trusted_key.is_some() {
+        check_payload(png, chunk_type, &payload, trusted_key)?;
+    }
+
+    let message = match (&payload.encryption, identity) {
+        (None, _) => payload.message,
+        (Some(encryption), Some(identity)) => {
+            encryption::decrypt(encryption, &payload.message, identity)?
+        }
+        (Some(_), None) => return Err(Error::EncryptedPayload.into()),
+    };
+    let message = match payload.compressed {
+        true => payload::decompress(&message)?,
+        false => message,
+    };
+    match payload.content_type {
+        ContentType::Text => Ok(String::from_utf8(message)?),
+        ContentType::Binary => Ok(hex::encode(message)),
+    }
+}
+
+pub fn verify(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    method: Method,
+    verify_key: Option<PathBuf>,
+    key: Option<String>,
+) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(file_path)?;
+    let png = Png::try_from(contents.as_slice())?;
+
+    let trusted_key = match &verify_key {
+        Some(path) => Some(signing::read_verifying_key(path)?),
+        None => None,
+    };
+
+    Payload::reassemble(hidden_data(&png, &chunk_type, method, key.as_deref())?)
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let data = data?;
+            if !Payload::is_payload(&data) {
+                return Err(Error::UnsignedPayload.into());
+            }
+
+            let payload = Payload::try_from(data.as_slice())?;
+            let findings = check_payload(&png, &chunk_type, &payload, trusted_key.as_ref())?;
+            if findings.is_empty() {
+                return Err(Error::UnsignedPayload.into());
+            }
+            Ok(format!("payload {}: {}", index, findings.join(", ")))
+        })
+        .collect()
+}
+
+fn check_payload(
+    png: &Png,
+    chunk_type: &ChunkType,
+    payload: &Payload,
+    trusted_key: Option<&VerifyingKey>,
+) -> anyhow::Result<Vec<String>> {
+    let mut findings = Vec::new();
+
+    if payload.signature.is_some() {
+        let digest = signing::critical_chunks_digest(png, chunk_type);
+        let signer = signing::verify(payload, trusted_key, Some(digest))?;
+        findings.push(format!(
+            "valid signature by {}",
+            hex::encode(signer.to_bytes())
+        ));
+        if payload.critical_digest.is_some() {
+            findings.push("critical chunks unchanged".to_string());
+        }
+    } else if trusted_key.is_some() {
+        return Err(Error::UnsignedPayload.into());
+    }
+
+    if let Some(expected) = payload.image_digest {
+        if signing::image_digest(png)? != expected {
+            return Err(Error::ImageContentModified.into());
+        }
+        findings.push("image content unchanged".to_string());
+    }
+
+    Ok(findings)
+}
+
+pub fn keygen(output_file: PathBuf, kind: KeyKind, force: bool) -> anyhow::Result<String> {
+    match kind {
+        KeyKind::Signing => {
+            let key = signing::generate_signing_key();
+            keys::write_key_pair(
+                &output_file,
+                key.to_bytes(),
+                key.verifying_key().to_bytes(),
+                force,
+            )?;
+        }
+        KeyKind::Encryption => {
+            let identity = encryption::generate_identity();
+            keys::write_key_pair(
+                &output_file,
+                identity.to_bytes(),
+                x25519_dalek::PublicKey::from(&identity).to_bytes(),
+                force,
+            )?;
+        }
+    }
+
+    Ok(format!(
+        "Wrote {} key to {} and public key to {}",
+        match kind {
+            KeyKind::Signing => "signing",
+            KeyKind::Encryption => "encryption",
+        },
+        output_file.display(),
+        keys::public_key_path(&output_file).display()
+    ))
+}
+
+fn hidden_data(
+    png: &Png,
+    chunk_type: &ChunkType,
+    method: Method,
+    key: Option<&str>,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    match method {
+        Method::Chunk => {
+            let chunks = png
+                .chunk_by_type(&chunk_type.to_string())
+                .ok_or(Error::ChunkTypeNotFound(chunk_type.to_string()))?;
+            Ok(chunks.iter().map(|chunk| chunk.data().to_vec()).collect())
+        }
+        Method::FrameTiming => Ok(vec![apng::extract_from_frame_timing(png)?]),
+        Method::Lsb => Ok(vec![lsb::extract(&RawImage::from_png(png)?, key)?]),
+        Method::Palette => Ok(vec![palette::extract(png, key)?]),
+    }
+}
 
-    Ok(data)
+pub fn repair(file_path: PathBuf, output_file: Option<PathBuf>) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(&file_path)?;
+    let (png, fixes) = Png::parse(&contents, ParseOptions::lenient())?;
+    if fixes.is_empty() {
+        return Ok(vec!["no problems found".to_string()]);
+    }
+
+    let mut lines: Vec<String> = fixes.iter().map(|fix| fix.to_string()).collect();
+    let output_file = output_file.unwrap_or(file_path);
+    fs::write(&output_file, png.as_bytes())?;
+    lines.push(format!(
+        "wrote {} chunks to {}",
+        png.chunks().len(),
+        output_file.display()
+    ));
+    Ok(lines)
+}
+
+pub fn dump(
+    file_path: PathBuf,
+    verify_crc: VerifyCrc,
+    options: DumpOptions,
+) -> anyhow::Result<Dump> {
+    let contents = fs::read(file_path)?;
+    let (png, fixes) = match verify_crc {
+        VerifyCrc::Error => (Png::try_from(contents.as_slice())?, Vec::new()),
+        verify_crc => Png::parse(
+            &contents,
+            ParseOptions {
+                verify_crc,
+                resync: true,
+            },
+        )?,
+    };
+    Ok(dump::dump(&png, &fixes, &options)?)
+}
+
+// Data files go in `data_dir`, relative to the manifest.
+pub fn export_manifest(
+    file_path: PathBuf,
+    output_file: PathBuf,
+    data_dir: Option<PathBuf>,
+) -> anyhow::Result<Vec<String>> {
+    // build only reads files inside the manifest's directory
+    if let Some(dir) = data_dir.as_deref().filter(|dir| !manifest::inside_dir(dir)) {
+        return Err(Error::InvalidManifest(format!(
+            "data dir `{}` must be a relative path inside the manifest's directory",
+            dir.display()
+        ))
+        .into());
+    }
+    let contents = fs::read(file_path)?;
+    let png = Png::try_from(contents.as_slice())?;
+
+    let (manifest, files) = Manifest::from_png(&png, data_dir.as_deref());
+    let base_dir = output_file.parent().unwrap_or(Path::new("."));
+    for (path, data) in &files {
+        let path = base_dir.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, data)?;
+    }
+    fs::write(
+        &output_file,
+        serde_json::to_string_pretty(&manifest)? + "\n",
+    )?;
+
+    Ok(vec![format!(
+        "wrote manifest of {} chunks{} to {}",
+        manifest.chunks.len(),
+        match files.len() {
+            0 => String::new(),
+            count => format!(" and {} data files", count),
+        },
+        output_file.display()
+    )])
+}
+
+pub fn build_manifest(file_path: PathBuf, output_file: PathBuf) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read_to_string(&file_path)?;
+    let manifest: Manifest =
+        serde_json::from_str(&contents).map_err(|err| Error::InvalidManifest(err.to_string()))?;
+
+    let bytes = manifest.build(file_path.parent().unwrap_or(Path::new(".")))?;
+    fs::write(&output_file, &bytes)?;
+    Ok(vec![format!(
+        "built {} chunks, {} bytes, to {}",
+        manifest.chunks.len(),
+        bytes.len(),
+        output_file.display()
+    )])
+}
+
+pub fn diff(a: PathBuf, b: PathBuf) -> anyhow::Result<Diff> {
+    let a = fs::read(a)?;
+    let b = fs::read(b)?;
+    Ok(diff::diff(
+        &Png::try_from(a.as_slice())?,
+        &Png::try_from(b.as_slice())?,
+    ))
+}
+
+pub fn transplant(
+    file_path: PathBuf,
+    source: PathBuf,
+    output_file: Option<PathBuf>,
+    chunk_types: Vec<ChunkType>,
+    force: bool,
+) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(&file_path)?;
+    let mut png = Png::try_from(contents.as_slice())?;
+    let source = fs::read(source)?;
+    let source = Png::try_from(source.as_slice())?;
+
+    let outcomes = transplant::transplant(&source, &mut png, &chunk_types, force)?;
+    let mut lines: Vec<String> = outcomes.iter().map(|outcome| outcome.to_string()).collect();
+    if lines.is_empty() {
+        lines.push("no ancillary chunks to copy".to_string());
+    }
+    let output_file = output_file.unwrap_or(file_path);
+    fs::write(&output_file, png.as_bytes())?;
+    lines.push(format!(
+        "wrote {} chunks to {}",
+        png.chunks().len(),
+        output_file.display()
+    ));
+    Ok(lines)
+}
+
+// Anything but strict CRC checking also resyncs past damage, so whatever
+// survived can still be read.
+fn read_png(contents: &[u8], verify_crc: VerifyCrc) -> anyhow::Result<Png> {
+    match verify_crc {
+        VerifyCrc::Error => Png::try_from(contents),
+        verify_crc => {
+            let options = ParseOptions {
+                verify_crc,
+                resync: true,
+            };
+            Ok(Png::parse(contents, options)?.0)
+        }
+    }
 }
 
-pub fn remove(file_path: PathBuf, chunk_type: ChunkType) -> anyhow::Result<()> {
+pub fn remove(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    name: Option<String>,
+    passphrase: Option<String>,
+) -> anyhow::Result<Notes> {
     let contents = fs::read(&file_path)?;
     let mut png = Png::try_from(contents.as_slice())?;
 
-    png.remove_chunk(&chunk_type.to_string())?;
+    let mut notes = Notes::default();
+    match name {
+        Some(name) => {
+            let entries = entries::entries(&png, &chunk_type, passphrase.as_deref())?;
+            let entry = entries::find(&entries, &name)?;
+            if entry.sealed {
+                notes.warnings.push(format!(
+                    "removed the whole container sealing `{}`, with any other messages in its slots",
+                    name
+                ));
+            }
+            png = Png::from_chunks(without_chunks(&png, &entry.chunks));
+        }
+        // the first entry goes whole, fragments and all
+        None => {
+            let entries = entries::entries(&png, &chunk_type, None)?;
+            png = Png::from_chunks(without_chunks(&png, &entries[0].chunks));
+        }
+    }
     let clean_png = png.as_bytes();
 
     fs::write(file_path, clean_png)?;
-    Ok(())
+    Ok(notes)
+}
+
+fn without_chunks(png: &Png, indices: &[usize]) -> Vec<Chunk> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !indices.contains(index))
+        .map(|(_, chunk)| chunk.clone())
+        .collect()
+}
+
+pub fn list(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    passphrase: Option<String>,
+) -> anyhow::Result<Listing> {
+    let contents = fs::read(file_path)?;
+    let png = Png::try_from(contents.as_slice())?;
+    Ok(entries::list(&entries::entries(
+        &png,
+        &chunk_type,
+        passphrase.as_deref(),
+    )?))
+}
+
+// Replaces the named message's chunks with the new message, in the place the
+// first of them held unless a placement is given. A message sealed with
+// `options.passphrase` is found, and its replacement sealed, with it.
+pub fn update(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    msg: String,
+    output_file: Option<PathBuf>,
+    placement: Option<Placement>,
+    options: EncodeOptions,
+) -> anyhow::Result<Notes> {
+    let contents = fs::read(&file_path)?;
+    let png = Png::try_from(contents.as_slice())?;
+
+    let Some(name) = &options.name else {
+        return Err(Error::IncompatibleOptions("update needs a message --name").into());
+    };
+    let existing = entries::entries(&png, &chunk_type, options.passphrase.as_deref())?;
+    let entry = entries::find(&existing, name)?;
+    // slots can't be opened without their passphrases, so only the decoys
+    // given again survive the reseal
+    let mut warnings = Vec::new();
+    if entry.sealed && options.decoy_messages.is_empty() {
+        warnings.push(format!(
+            "resealed `{}` alone, any other messages in its container's slots are lost unless given again with --decoy-message",
+            name
+        ));
+    }
+
+    let msg = frame_message(&png, &chunk_type, msg.into_bytes(), &options)?;
+    let msg = seal_message(msg, &options)?;
+    let size = msg.len();
+    let replaced = format!(
+        "replaced `{}` in {} {} chunks",
+        name,
+        entry.chunks.len(),
+        chunk_type
+    );
+    let mut png = Png::from_chunks(without_chunks(&png, &entry.chunks));
+    let report = match placement {
+        Some(placement) => match options.fragment_size {
+            Some(size) => insert_fragments(&mut png, &chunk_type, &msg, size, placement)?,
+            None => insert_chunk_at(&mut png, Chunk::new(chunk_type.clone(), msg), placement),
+        },
+        None => {
+            let replacement = message_chunks(&chunk_type, msg, options.fragment_size)?;
+            let count = replacement.len();
+            let index = entry.chunks[0];
+            let mut chunks = png.chunks().to_vec();
+            chunks.splice(index..index, replacement);
+            png = Png::from_chunks(chunks);
+            format!("{} bytes in {} chunks at chunk {}", size, count, index)
+        }
+    };
+
+    fs::write(output_file.unwrap_or(file_path), png.as_bytes())?;
+    Ok(Notes {
+        report: vec![format!("{} with {}", replaced, report)],
+        warnings,
+    })
+}
+
+// Copies the `index`th chunk of `chunk_type` out, as just its data or, with
+// `whole`, with its length, type and CRC so `insert_chunk` can read it back.
+pub fn extract_chunk(
+    file_path: PathBuf,
+    chunk_type: ChunkType,
+    index: usize,
+    output_file: PathBuf,
+    whole: bool,
+) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(file_path)?;
+    let png = Png::try_from(contents.as_slice())?;
+
+    let chunks = png
+        .chunk_by_type(&chunk_type.to_string())
+        .ok_or(Error::ChunkTypeNotFound(chunk_type.to_string()))?;
+    let chunk = chunks.get(index).ok_or(Error::ChunkIndexNotFound {
+        chunk_type: chunk_type.to_string(),
+        index,
+        count: chunks.len(),
+    })?;
+    let bytes = match whole {
+        true => chunk.as_bytes(),
+        false => chunk.data().to_vec(),
+    };
+    fs::write(&output_file, &bytes)?;
+
+    Ok(vec![format!(
+        "wrote {} bytes of {} chunk {} of {}{} to {}",
+        bytes.len(),
+        chunk_type,
+        index,
+        chunks.len(),
+        if whole { " with its CRC" } else { "" },
+        output_file.display()
+    )])
+}
+
+// Without a `chunk_type` the file holds a whole chunk as written by
+// `extract_chunk --whole`, otherwise just the chunk's data.
+pub fn insert_chunk(
+    file_path: PathBuf,
+    chunk_file: PathBuf,
+    chunk_type: Option<ChunkType>,
+    output_file: Option<PathBuf>,
+    placement: Placement,
+) -> anyhow::Result<Vec<String>> {
+    let contents = fs::read(&file_path)?;
+    let mut png = Png::try_from(contents.as_slice())?;
+
+    let bytes = fs::read(chunk_file)?;
+    let chunk = match chunk_type {
+        Some(chunk_type) => Chunk::new(chunk_type, bytes),
+        None => {
+            let chunk = Chunk::try_from(bytes.as_slice())?;
+            if chunk.as_bytes().len() != bytes.len() {
+                return Err(
+                    Error::InvalidFile("chunk file has bytes after the chunk's CRC").into(),
+                );
+            }
+            chunk
+        }
+    };
+    let report = insert_chunk_at(&mut png, chunk, placement);
+
+    fs::write(output_file.unwrap_or(file_path), png.as_bytes())?;
+    Ok(vec![report])
+}
+
+#[derive(Debug, Serialize)]
+pub struct Inspection {
+    pub chunks: Vec<ChunkSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Fingerprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub animation: Option<AnimationSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkSummary {
+    pub index: usize,
+    pub chunk_type: String,
+    pub length: u32,
+    // the decoded contents of a known chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnimationSummary {
+    pub num_frames: u32,
+    // 0 loops forever
+    pub num_plays: u32,
+    pub frames: Vec<FrameSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FrameSummary {
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_seconds: f64,
+    pub data_chunks: usize,
+    pub is_default_image: bool,
+}
+
+impl Report for Inspection {
+    fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let summary = format!(
+                    "{:>4}  {}  {:>8} bytes",
+                    chunk.index, chunk.chunk_type, chunk.length
+                );
+                match (&chunk.contents, &chunk.error) {
+                    (Some(contents), _) => format!("{}  {}", summary, contents),
+                    (None, Some(error)) => format!("{}  {}", summary, error.message),
+                    (None, None) => summary,
+                }
+            })
+            .collect();
+
+        if let Some(fingerprint) = &self.compression {
+            lines.push(String::new());
+            lines.push(format!("compression: {}", fingerprint));
+        }
+
+        if let Some(animation) = &self.animation {
+            lines.push(String::new());
+            lines.push(format!(
+                "animation: {} frames, {}",
+                animation.num_frames,
+                match animation.num_plays {
+                    0 => "loops forever".to_string(),
+                    plays => format!("plays {} times", plays),
+                }
+            ));
+            for (index, frame) in animation.frames.iter().enumerate() {
+                lines.push(format!(
+                    "  frame {:>3}  {}x{} at ({}, {})  delay {:.3}s  {} data chunks{}",
+                    index,
+                    frame.width,
+                    frame.height,
+                    frame.x_offset,
+                    frame.y_offset,
+                    frame.delay_seconds,
+                    frame.data_chunks,
+                    if frame.is_default_image {
+                        "  (default image)"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+            match &animation.error {
+                None => lines.push("  sequence numbers valid".to_string()),
+                Some(error) => lines.push(format!("  {}", error.message)),
+            }
+        }
+
+        lines
+    }
+}
+
+pub fn inspect(file_path: PathBuf, verify_crc: VerifyCrc) -> anyhow::Result<Inspection> {
+    let contents = fs::read(file_path)?;
+    let png = read_png(&contents, verify_crc)?;
+    let color_type = png.image_header().ok().map(|header| header.color_type);
+
+    let chunks = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let (contents, error) = match chunk.parse_known_with(color_type) {
+                Ok(KnownChunk::Unknown(_)) => (None, None),
+                Ok(known) => (Some(known.to_string()), None),
+                Err(err) => (None, Some(ErrorObject::from(&err))),
+            };
+            ChunkSummary {
+                index,
+                chunk_type: chunk.chunk_type().to_string(),
+                length: chunk.length(),
+                contents,
+                error,
+            }
+        })
+        .collect();
+
+    let animation = Animation::from_png(&png)?.map(|animation| AnimationSummary {
+        num_frames: animation.num_frames,
+        num_plays: animation.num_plays,
+        frames: animation
+            .frames
+            .iter()
+            .map(|frame| FrameSummary {
+                width: frame.control.width,
+                height: frame.control.height,
+                x_offset: frame.control.x_offset,
+                y_offset: frame.control.y_offset,
+                delay_seconds: frame.control.delay_seconds(),
+                data_chunks: frame.data_chunks,
+                is_default_image: frame.is_default_image,
+            })
+            .collect(),
+        error: animation
+            .validate(&png)
+            .err()
+            .map(|err| ErrorObject::from(&err)),
+    });
+
+    Ok(Inspection {
+        chunks,
+        compression: Fingerprint::from_png(&png).ok(),
+        animation,
+    })
 }
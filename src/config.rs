@@ -0,0 +1,279 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk_type::ChunkType, commands::Method, errors::Error, output::Report, png::Placement,
+};
+
+// Looked up in the working directory and then its parents.
+const PROJECT_FILE: &str = "hidepng.toml";
+const DEFAULT_CHUNK_TYPE: &str = "STXT";
+
+// Defaults for command line options. Unset fields fall through to the next
+// file, and flags given on the command line win over all of them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub chunk_type: Option<String>,
+    pub method: Option<Method>,
+    pub placement: Option<Placement>,
+    pub sign_key: Option<PathBuf>,
+    pub verify_key: Option<PathBuf>,
+    pub identity: Option<PathBuf>,
+    pub recipients: Vec<PathBuf>,
+}
+
+impl Settings {
+    fn merge(&mut self, other: Settings) {
+        self.chunk_type = other.chunk_type.or(self.chunk_type.take());
+        self.method = other.method.or(self.method);
+        self.placement = other.placement.or(self.placement);
+        self.sign_key = other.sign_key.or(self.sign_key.take());
+        self.verify_key = other.verify_key.or(self.verify_key.take());
+        self.identity = other.identity.or(self.identity.take());
+        if !other.recipients.is_empty() {
+            self.recipients = other.recipients;
+        }
+    }
+
+    // Key paths are relative to the file that names them.
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in [&mut self.sign_key, &mut self.verify_key, &mut self.identity]
+            .into_iter()
+            .flatten()
+            .chain(self.recipients.iter_mut())
+        {
+            *path = dir.join(&path);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub files: Vec<PathBuf>,
+    pub profile: Option<String>,
+    pub settings: Settings,
+}
+
+impl Config {
+    // Reads the user's config file and then the project's, applying the named
+    // profile from either on top.
+    pub fn load(profile: Option<&str>) -> Result<Config, Error> {
+        let files: Vec<PathBuf> = [user_file(), project_file()]
+            .into_iter()
+            .flatten()
+            .filter(|path| path.is_file())
+            .collect();
+
+        let mut settings = Settings::default();
+        let mut profiles: BTreeMap<String, Settings> = BTreeMap::new();
+        for path in &files {
+            let contents = fs::read_to_string(path).map_err(|err| invalid(path, err))?;
+            let (mut defaults, named) = parse(&contents).map_err(|err| invalid(path, err))?;
+            let dir = path.parent().unwrap_or(Path::new("."));
+            defaults.resolve_paths(dir);
+            settings.merge(defaults);
+            for (name, mut named) in named {
+                named.resolve_paths(dir);
+                profiles.entry(name).or_default().merge(named);
+            }
+        }
+
+        if let Some(name) = profile {
+            let named = profiles
+                .remove(name)
+                .ok_or(Error::UnknownProfile(name.to_string()))?;
+            settings.merge(named);
+        }
+
+        Ok(Config {
+            files,
+            profile: profile.map(str::to_string),
+            settings,
+        })
+    }
+
+    pub fn chunk_type(&self, flag: Option<ChunkType>) -> ChunkType {
+        // checked when the file was read
+        flag.unwrap_or_else(|| {
+            ChunkType::from_str(
+                self.settings
+                    .chunk_type
+                    .as_deref()
+                    .unwrap_or(DEFAULT_CHUNK_TYPE),
+            )
+            .unwrap()
+        })
+    }
+
+    pub fn method(&self, flag: Option<Method>) -> Method {
+        flag.or(self.settings.method).unwrap_or(Method::Chunk)
+    }
+
+    pub fn placement(&self, flag: Option<Placement>) -> Placement {
+        flag.or(self.settings.placement).unwrap_or_default()
+    }
+
+    // Configured recipients give way to a passphrase on the command line.
+    pub fn recipients(&self, flag: Vec<PathBuf>, passphrase: bool) -> Vec<PathBuf> {
+        if flag.is_empty() && !passphrase {
+            return self.settings.recipients.clone();
+        }
+        flag
+    }
+
+    pub fn effective(&self) -> Effective {
+        Effective {
+            files: self.files.clone(),
+            profile: self.profile.clone(),
+            chunk_type: self.chunk_type(None).to_string(),
+            method: self.method(None),
+            placement: self.placement(None),
+            sign_key: self.settings.sign_key.clone(),
+            verify_key: self.settings.verify_key.clone(),
+            identity: self.settings.identity.clone(),
+            recipients: self.settings.recipients.clone(),
+        }
+    }
+}
+
+// The settings a command runs with when no flags are given.
+#[derive(Debug, Serialize)]
+pub struct Effective {
+    pub files: Vec<PathBuf>,
+    pub profile: Option<String>,
+    pub chunk_type: String,
+    pub method: Method,
+    pub placement: Placement,
+    pub sign_key: Option<PathBuf>,
+    pub verify_key: Option<PathBuf>,
+    pub identity: Option<PathBuf>,
+    pub recipients: Vec<PathBuf>,
+}
+
+impl Report for Effective {
+    fn lines(&self) -> Vec<String> {
+        let list = |paths: &[PathBuf]| match paths {
+            [] => "none".to_string(),
+            paths => paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        let key = |path: &Option<PathBuf>| match path {
+            Some(path) => path.display().to_string(),
+            None => "not set".to_string(),
+        };
+        vec![
+            format!("config files: {}", list(&self.files)),
+            format!("profile: {}", self.profile.as_deref().unwrap_or("none")),
+            format!("chunk-type: {}", self.chunk_type),
+            format!(
+                "method: {}",
+                self.method.to_possible_value().unwrap().get_name()
+            ),
+            format!(
+                "placement: {}",
+                self.placement.to_possible_value().unwrap().get_name()
+            ),
+            format!("sign-key: {}", key(&self.sign_key)),
+            format!("verify-key: {}", key(&self.verify_key)),
+            format!("identity: {}", key(&self.identity)),
+            format!("recipients: {}", list(&self.recipients)),
+        ]
+    }
+}
+
+// Top level settings, and the `[profile.<name>]` tables.
+fn parse(contents: &str) -> Result<(Settings, BTreeMap<String, Settings>), String> {
+    let mut table: toml::Table = contents.parse().map_err(|err| format!("{}", err))?;
+    let profiles: BTreeMap<String, Settings> = match table.remove("profile") {
+        Some(profiles) => profiles.try_into().map_err(|err| format!("{}", err))?,
+        None => BTreeMap::new(),
+    };
+    let settings: Settings = toml::Value::Table(table)
+        .try_into()
+        .map_err(|err| format!("{}", err))?;
+
+    for chunk_type in std::iter::once(&settings)
+        .chain(profiles.values())
+        .filter_map(|settings| settings.chunk_type.as_deref())
+    {
+        ChunkType::from_str(chunk_type).map_err(|err| err.to_string())?;
+    }
+    Ok((settings, profiles))
+}
+
+fn invalid(path: &Path, reason: impl ToString) -> Error {
+    Error::InvalidConfig {
+        path: path.display().to_string(),
+        reason: reason
+            .to_string()
+            .trim_end()
+            .trim_end_matches('.')
+            .to_string(),
+    }
+}
+
+fn user_file() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("hidepng").join("config.toml"))
+}
+
+fn project_file() -> Option<PathBuf> {
+    env::current_dir()
+        .ok()?
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings_and_profiles() {
+        let (settings, profiles) = parse(
+            r#"
+            chunk-type = "TXTI"
+            method = "lsb"
+            recipients = ["alice.pub"]
+
+            [profile.work]
+            placement = "before-end"
+            sign-key = "/keys/work.key"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings.chunk_type.as_deref(), Some("TXTI"));
+        assert_eq!(settings.method, Some(Method::Lsb));
+        assert_eq!(profiles["work"].placement, Some(Placement::BeforeEnd));
+
+        let mut merged = settings.clone();
+        merged.resolve_paths(Path::new("/project"));
+        merged.merge(profiles["work"].clone());
+        assert_eq!(merged.chunk_type.as_deref(), Some("TXTI"));
+        assert_eq!(merged.recipients, [PathBuf::from("/project/alice.pub")]);
+        assert_eq!(merged.sign_key, Some(PathBuf::from("/keys/work.key")));
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_settings() {
+        assert!(parse("chunk-typ = \"TXTI\"").is_err());
+        assert!(parse("chunk-type = \"Rust\"").is_err());
+        assert!(parse("method = \"carrier-pigeon\"").is_err());
+        assert!(parse("[profile.work]\nchunk-type = 4").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload as AeadPayload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{rngs::OsRng, seq::SliceRandom, RngCore};
+
+use crate::errors::Error;
+
+// Every container holds this many slots whether they carry a message or not,
+// so the number of real payloads can't be read off the size.
+pub const SLOTS: usize = 4;
+
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+const LENGTH_SIZE: usize = 4;
+// Slot plaintexts are padded up to a multiple of this.
+const BUCKET_SIZE: usize = 256;
+
+// Layout: salt, then SLOTS equally sized slots of nonce || ciphertext || tag.
+// Unused slots are filled with random bytes, which are indistinguishable from
+// sealed ones without the passphrase.
+pub fn seal(entries: &[(&[u8], &str)]) -> Result<Vec<u8>, Error> {
+    if entries.is_empty() || entries.len() > SLOTS {
+        return Err(Error::InvalidDecoys(format!(
+            "between 1 and {} messages can share an image, got {}",
+            SLOTS,
+            entries.len()
+        )));
+    }
+    for (index, (_, passphrase)) in entries.iter().enumerate() {
+        if entries[..index]
+            .iter()
+            .any(|(_, other)| other == passphrase)
+        {
+            return Err(Error::InvalidDecoys(
+                "every message needs a different passphrase".to_string(),
+            ));
+        }
+    }
+
+    let longest = entries.iter().map(|(message, _)| message.len()).max();
+    let plaintext_size = plaintext_size(longest.unwrap_or(0));
+    let slot_size = NONCE_SIZE + plaintext_size + TAG_SIZE;
+
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut order: Vec<usize> = (0..SLOTS).collect();
+    order.shuffle(&mut OsRng);
+
+    let mut slots = vec![Vec::new(); SLOTS];
+    for ((message, passphrase), slot) in entries.iter().zip(order) {
+        let mut plaintext = (message.len() as u32).to_be_bytes().to_vec();
+        plaintext.extend(*message);
+        plaintext.resize(plaintext_size, 0);
+
+        let mut nonce = [0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?)
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                AeadPayload {
+                    msg: &plaintext,
+                    aad: &salt,
+                },
+            )
+            .expect("encrypting into a Vec cannot fail");
+
+        slots[slot] = nonce.to_vec();
+        slots[slot].extend(ciphertext);
+    }
+
+    let mut container = salt.to_vec();
+    for mut slot in slots {
+        if slot.is_empty() {
+            slot = vec![0u8; slot_size];
+            OsRng.fill_bytes(&mut slot);
+        }
+        container.extend(slot);
+    }
+    Ok(container)
+}
+
+pub fn open(container: &[u8], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let minimum_slot = NONCE_SIZE + LENGTH_SIZE + TAG_SIZE;
+    if container.len() < SALT_SIZE + SLOTS * minimum_slot
+        || !(container.len() - SALT_SIZE).is_multiple_of(SLOTS)
+    {
+        return Err(Error::DecryptionFailed);
+    }
+
+    let (salt, slots) = container.split_at(SALT_SIZE);
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+
+    let plaintext = slots
+        .chunks(slots.len() / SLOTS)
+        .find_map(|slot| {
+            let (nonce, ciphertext) = slot.split_at(NONCE_SIZE);
+            cipher
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    AeadPayload {
+                        msg: ciphertext,
+                        aad: salt,
+                    },
+                )
+                .ok()
+        })
+        .ok_or(Error::DecryptionFailed)?;
+
+    let (length, message) = plaintext.split_at(LENGTH_SIZE);
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    message
+        .get(..length)
+        .map(|message| message.to_vec())
+        .ok_or(Error::DecryptionFailed)
+}
+
+// Size of the container `seal` produces when the longest message is `length`
+// bytes.
+pub fn sealed_size(length: usize) -> usize {
+    SALT_SIZE + SLOTS * (NONCE_SIZE + plaintext_size(length) + TAG_SIZE)
+}
+
+// Longest message that seals into at most `size` bytes.
+pub fn capacity(size: usize) -> Option<usize> {
+    let slot = size.checked_sub(SALT_SIZE)? / SLOTS;
+    let plaintext = slot.checked_sub(NONCE_SIZE + TAG_SIZE)? / BUCKET_SIZE * BUCKET_SIZE;
+    plaintext.checked_sub(LENGTH_SIZE)
+}
+
+fn plaintext_size(length: usize) -> usize {
+    (length + LENGTH_SIZE).div_ceil(BUCKET_SIZE) * BUCKET_SIZE
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, Error> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| Error::InvalidDecoys(err.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_each_passphrase_opens_its_message() {
+        let container = seal(&[
+            (b"the real plans".as_slice(), "correct horse"),
+            (b"grocery list".as_slice(), "battery staple"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            open(&container, "correct horse").unwrap(),
+            b"the real plans"
+        );
+        assert_eq!(open(&container, "battery staple").unwrap(), b"grocery list");
+        assert!(matches!(
+            open(&container, "wrong"),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_size_does_not_reveal_message_count() {
+        let one = seal(&[(b"short".as_slice(), "a")]).unwrap();
+        let three = seal(&[
+            (b"short".as_slice(), "a"),
+            (b"also short".as_slice(), "b"),
+            (b"tiny".as_slice(), "c"),
+        ])
+        .unwrap();
+        assert_eq!(one.len(), three.len());
+    }
+
+    #[test]
+    fn test_sealed_size_and_capacity() {
+        let container = seal(&[(b"sized".as_slice(), "a")]).unwrap();
+        assert_eq!(container.len(), sealed_size(5));
+
+        for size in [
+            0,
+            100,
+            sealed_size(0),
+            sealed_size(300),
+            sealed_size(300) + 1000,
+        ] {
+            match capacity(size) {
+                Some(length) => {
+                    assert!(sealed_size(length) <= size);
+                    assert!(sealed_size(length + 1) > size);
+                }
+                None => assert!(sealed_size(0) > size),
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_duplicate_passphrases() {
+        assert!(seal(&[(b"one".as_slice(), "same"), (b"two".as_slice(), "same")]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_too_many_messages() {
+        let entries: Vec<(&[u8], &str)> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|passphrase| (b"message".as_slice(), *passphrase))
+            .collect();
+        assert!(seal(&entries).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use serde::Serialize;
+
+use crate::{
+    errors::Error,
+    image::{channels, RawImage},
+    known_chunk::ColorType,
+    output::Report,
+    png::Png,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+// Chunks are paired by type and by their position among chunks of that type,
+// so the first tEXt of one file is compared with the first tEXt of the other.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChunkChange {
+    pub change: Change,
+    pub chunk_type: String,
+    pub occurrence: usize,
+    pub index_a: Option<usize>,
+    pub index_b: Option<usize>,
+    pub length_a: Option<u32>,
+    pub length_b: Option<u32>,
+    pub crc_a: Option<u32>,
+    pub crc_b: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoundingBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum PixelDiff {
+    Identical,
+    Different {
+        changed_samples: usize,
+        changed_pixels: usize,
+        bounding_box: BoundingBox,
+        max_delta: u16,
+    },
+    Incomparable {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diff {
+    pub identical: bool,
+    pub chunks: Vec<ChunkChange>,
+    pub unchanged: usize,
+    // only when the image data, header or palette changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pixels: Option<PixelDiff>,
+}
+
+impl Report for Diff {
+    fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .chunks
+            .iter()
+            .map(|change| {
+                let side = |index: Option<usize>, length: Option<u32>, crc: Option<u32>| {
+                    format!(
+                        "chunk {}, {} bytes, crc {:08x}",
+                        index.unwrap_or_default(),
+                        length.unwrap_or_default(),
+                        crc.unwrap_or_default()
+                    )
+                };
+                let a = side(change.index_a, change.length_a, change.crc_a);
+                let b = side(change.index_b, change.length_b, change.crc_b);
+                let label = format!("{} {}", change.chunk_type, change.occurrence);
+                match change.change {
+                    Change::Added => format!("added    {}: {}", label, b),
+                    Change::Removed => format!("removed  {}: {}", label, a),
+                    Change::Modified => format!("modified {}: {} -> {}", label, a, b),
+                }
+            })
+            .collect();
+        lines.push(format!("{} chunks unchanged", self.unchanged));
+
+        match &self.pixels {
+            None => {}
+            Some(PixelDiff::Identical) => lines.push("pixels: identical".to_string()),
+            Some(PixelDiff::Different {
+                changed_samples,
+                changed_pixels,
+                bounding_box,
+                max_delta,
+            }) => lines.push(format!(
+                "pixels: {} samples of {} pixels changed within {}x{} at ({}, {}), max delta {}",
+                changed_samples,
+                changed_pixels,
+                bounding_box.width,
+                bounding_box.height,
+                bounding_box.x,
+                bounding_box.y,
+                max_delta
+            )),
+            Some(PixelDiff::Incomparable { reason }) => {
+                lines.push(format!("pixels: not compared, {}", reason))
+            }
+        }
+        lines
+    }
+}
+
+pub fn diff(a: &Png, b: &Png) -> Diff {
+    let mut chunks = Vec::new();
+    let mut unchanged = 0;
+    let mut types: Vec<String> = Vec::new();
+    for chunk in a.chunks().iter().chain(b.chunks()) {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !types.contains(&chunk_type) {
+            types.push(chunk_type);
+        }
+    }
+
+    let mut image_changed = false;
+    for chunk_type in &types {
+        let of_type = |png: &Png| -> Vec<usize> {
+            png.chunks()
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| chunk.chunk_type().to_string() == *chunk_type)
+                .map(|(index, _)| index)
+                .collect()
+        };
+        let (in_a, in_b) = (of_type(a), of_type(b));
+        for occurrence in 0..in_a.len().max(in_b.len()) {
+            let index_a = in_a.get(occurrence).copied();
+            let index_b = in_b.get(occurrence).copied();
+            let chunk_a = index_a.map(|index| &a.chunks()[index]);
+            let chunk_b = index_b.map(|index| &b.chunks()[index]);
+            let change = match (chunk_a, chunk_b) {
+                (Some(x), Some(y)) if x.data() == y.data() => {
+                    unchanged += 1;
+                    continue;
+                }
+                (Some(_), Some(_)) => Change::Modified,
+                (Some(_), None) => Change::Removed,
+                _ => Change::Added,
+            };
+            image_changed |= matches!(chunk_type.as_str(), "IHDR" | "PLTE" | "IDAT");
+            chunks.push(ChunkChange {
+                change,
+                chunk_type: chunk_type.clone(),
+                occurrence,
+                index_a,
+                index_b,
+                length_a: chunk_a.map(|chunk| chunk.length()),
+                length_b: chunk_b.map(|chunk| chunk.length()),
+                crc_a: chunk_a.map(|chunk| chunk.crc()),
+                crc_b: chunk_b.map(|chunk| chunk.crc()),
+            });
+        }
+    }
+
+    let pixels = image_changed.then(|| {
+        compare_pixels(a, b).unwrap_or_else(|err| PixelDiff::Incomparable {
+            reason: err.to_string(),
+        })
+    });
+    Diff {
+        identical: chunks.is_empty(),
+        chunks,
+        unchanged,
+        pixels,
+    }
+}
+
+fn compare_pixels(a: &Png, b: &Png) -> Result<PixelDiff, Error> {
+    let (image_a, image_b) = (RawImage::from_png(a)?, RawImage::from_png(b)?);
+    let (header_a, header_b) = (&image_a.header, &image_b.header);
+    if (header_a.width, header_a.height) != (header_b.width, header_b.height) {
+        return Ok(PixelDiff::Incomparable {
+            reason: format!(
+                "sizes differ, {}x{} and {}x{}",
+                header_a.width, header_a.height, header_b.width, header_b.height
+            ),
+        });
+    }
+    if (header_a.color_type, header_a.bit_depth) != (header_b.color_type, header_b.bit_depth) {
+        return Ok(PixelDiff::Incomparable {
+            reason: format!(
+                "formats differ, {}-bit {} and {}-bit {}",
+                header_a.bit_depth, header_a.color_type, header_b.bit_depth, header_b.color_type
+            ),
+        });
+    }
+
+    let (samples_a, samples_b) = (samples(a, &image_a)?, samples(b, &image_b)?);
+    let per_pixel = samples_a.len() / (header_a.width as usize * header_a.height as usize);
+    let mut changed_samples = 0;
+    let mut changed_pixels = 0;
+    let mut max_delta = 0;
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (pixel, (x, y)) in samples_a
+        .chunks(per_pixel)
+        .zip(samples_b.chunks(per_pixel))
+        .enumerate()
+    {
+        let changed = x.iter().zip(y).filter(|(x, y)| x != y).count();
+        if changed == 0 {
+            continue;
+        }
+        changed_samples += changed;
+        changed_pixels += 1;
+        max_delta = x
+            .iter()
+            .zip(y)
+            .map(|(x, y)| x.abs_diff(*y))
+            .fold(max_delta, u16::max);
+        let (column, row) = (
+            (pixel % header_a.width as usize) as u32,
+            (pixel / header_a.width as usize) as u32,
+        );
+        (left, top) = (left.min(column), top.min(row));
+        (right, bottom) = (right.max(column), bottom.max(row));
+    }
+
+    if changed_pixels == 0 {
+        return Ok(PixelDiff::Identical);
+    }
+    Ok(PixelDiff::Different {
+        changed_samples,
+        changed_pixels,
+        bounding_box: BoundingBox {
+            x: left,
+            y: top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        },
+        max_delta,
+    })
+}
+
+// Palette indices are looked up, so reordering the palette alone is no change.
+fn samples(png: &Png, image: &RawImage) -> Result<Vec<u16>, Error> {
+    let count = image.sample_count();
+    if image.header.color_type != ColorType::Indexed {
+        return Ok((0..count).map(|index| image.sample(index)).collect());
+    }
+
+    let palette = png.palette()?.unwrap_or_default();
+    let mut samples = Vec::with_capacity(count * 3);
+    for index in 0..count / channels(ColorType::Indexed) {
+        let entry = palette
+            .get(image.sample(index) as usize)
+            .ok_or(Error::MalformedChunk {
+                chunk_type: "IDAT",
+                reason: "palette index out of range",
+            })?;
+        samples.extend([entry.red, entry.green, entry.blue].map(u16::from));
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, lsb::LsbOptions, palette};
+    use std::str::FromStr;
+
+    fn testing_png(path: &str) -> Png {
+        let contents = std::fs::read(path).unwrap();
+        Png::try_from(contents.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_diff_chunks() {
+        let a = testing_png("assets/catgurl.png");
+        assert!(diff(&a, &a).identical);
+
+        let mut b = testing_png("assets/catgurl.png");
+        b.insert_between(Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Comment\0hi".to_vec(),
+        ));
+        b.remove_chunk("IEND").unwrap();
+        let diff = diff(&a, &b);
+        assert!(!diff.identical);
+        assert_eq!(diff.unchanged, 6);
+        assert_eq!(
+            diff.chunks
+                .iter()
+                .map(|change| (change.change, change.chunk_type.as_str()))
+                .collect::<Vec<_>>(),
+            [(Change::Removed, "IEND"), (Change::Added, "tEXt")]
+        );
+        assert_eq!(diff.pixels, None);
+    }
+
+    #[test]
+    fn test_diff_pixels() {
+        let a = testing_png("assets/catgurl.png");
+        let mut b = testing_png("assets/catgurl.png");
+        palette::embed(&mut b, b"changed pixels", &LsbOptions::default()).unwrap();
+
+        let diff = diff(&a, &b);
+        match diff.pixels {
+            Some(PixelDiff::Different {
+                changed_samples,
+                changed_pixels,
+                max_delta,
+                ..
+            }) => {
+                assert!(changed_pixels > 0);
+                assert!(changed_samples >= changed_pixels);
+                assert!(max_delta > 0);
+            }
+            other => panic!("unexpected pixel diff {:?}", other),
+        }
+
+        let other = testing_png("assets/animegurl.png");
+        assert!(matches!(
+            super::diff(&a, &other).pixels,
+            Some(PixelDiff::Incomparable { .. })
+        ));
+    }
+}
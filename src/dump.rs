@@ -0,0 +1,311 @@
+use serde::{Serialize, Serializer};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    errors::Error,
+    known_chunk::{ColorType, KnownChunk},
+    output::Report,
+    payload::Payload,
+    png::{Fix, Png},
+};
+
+const BYTES_PER_LINE: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct DumpOptions {
+    pub chunk_type: Option<ChunkType>,
+    // which chunk of `chunk_type`, counting from 0
+    pub index: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub annotate: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Dump {
+    pub chunks: Vec<ChunkDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunkDump {
+    pub index: usize,
+    // of the length field, from the start of the file; None for a chunk the
+    // parser added, such as a missing IEND
+    pub offset: Option<usize>,
+    pub chunk_type: String,
+    pub length: u32,
+    pub crc: u32,
+    #[serde(serialize_with = "as_hex")]
+    pub data: Vec<u8>,
+    // bytes left out by --max-bytes
+    pub omitted: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<String>,
+}
+
+fn as_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+impl Report for Dump {
+    fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (n, chunk) in self.chunks.iter().enumerate() {
+            if n > 0 {
+                lines.push(String::new());
+            }
+            // the type was valid when the file was parsed
+            let chunk_type = chunk.chunk_type.parse::<ChunkType>().unwrap();
+            lines.push(format!(
+                "chunk {} {}: {}, {} bytes, crc {:08x}, {}, {}, {}",
+                chunk.index,
+                match chunk.offset {
+                    Some(offset) => format!("at byte {}", offset),
+                    None => "not in the file".to_string(),
+                },
+                chunk.chunk_type,
+                chunk.length,
+                chunk.crc,
+                if chunk_type.is_critical() {
+                    "critical"
+                } else {
+                    "ancillary"
+                },
+                if chunk_type.is_public() {
+                    "public"
+                } else {
+                    "private"
+                },
+                if chunk_type.is_safe_to_copy() {
+                    "safe to copy"
+                } else {
+                    "unsafe to copy"
+                }
+            ));
+            if let Some(annotation) = &chunk.annotation {
+                lines.push(format!("  = {}", annotation));
+            }
+            lines.extend(hex_lines(&chunk.data));
+            if chunk.omitted > 0 {
+                lines.push(format!("  ... {} more bytes", chunk.omitted));
+            }
+        }
+        lines
+    }
+}
+
+// Offset, hex bytes in two groups of eight, and printable ASCII.
+fn hex_lines(data: &[u8]) -> Vec<String> {
+    data.chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(line, bytes)| {
+            let hex: Vec<String> = (0..BYTES_PER_LINE)
+                .map(|i| match bytes.get(i) {
+                    Some(byte) => format!("{:02x}", byte),
+                    None => "  ".to_string(),
+                })
+                .collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            format!(
+                "  {:08x}  {}  {}  |{}|",
+                line * BYTES_PER_LINE,
+                hex[..8].join(" "),
+                hex[8..].join(" "),
+                ascii
+            )
+        })
+        .collect()
+}
+
+// `fixes` are what lenient parsing changed, so offsets can step over the bytes
+// it skipped.
+pub fn dump(png: &Png, fixes: &[Fix], options: &DumpOptions) -> Result<Dump, Error> {
+    let color_type = png.image_header().ok().map(|header| header.color_type);
+    let offsets = chunk_offsets(png, fixes);
+    let mut chunks = Vec::new();
+    for (index, (chunk, &chunk_offset)) in png.chunks().iter().zip(&offsets).enumerate() {
+        if let Some(chunk_type) = &options.chunk_type {
+            if chunk.chunk_type() != chunk_type {
+                continue;
+            }
+        }
+
+        let data = chunk.data();
+        let shown = options.max_bytes.unwrap_or(data.len()).min(data.len());
+        chunks.push(ChunkDump {
+            index,
+            offset: chunk_offset,
+            chunk_type: chunk.chunk_type().to_string(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            data: data[..shown].to_vec(),
+            omitted: data.len() - shown,
+            annotation: options
+                .annotate
+                .then(|| annotate(chunk, color_type))
+                .flatten(),
+        });
+    }
+
+    if let (Some(chunk_type), None) = (&options.chunk_type, options.index) {
+        if chunks.is_empty() {
+            return Err(Error::ChunkTypeNotFound(chunk_type.to_string()));
+        }
+    }
+    if let (Some(chunk_type), Some(index)) = (&options.chunk_type, options.index) {
+        let count = chunks.len();
+        if index >= count {
+            return Err(Error::ChunkIndexNotFound {
+                chunk_type: chunk_type.to_string(),
+                index,
+                count,
+            });
+        }
+        chunks = vec![chunks.swap_remove(index)];
+    }
+    Ok(Dump { chunks })
+}
+
+// The parser resyncs at the offset it skips from, so each skip lands exactly
+// between two chunks.
+fn chunk_offsets(png: &Png, fixes: &[Fix]) -> Vec<Option<usize>> {
+    let mut offset = png.header().len();
+    let mut offsets: Vec<Option<usize>> = png
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            for fix in fixes {
+                if let Fix::Skipped {
+                    offset: from,
+                    length,
+                } = fix
+                {
+                    if *from == offset {
+                        offset += length;
+                    }
+                }
+            }
+            let chunk_offset = offset;
+            offset += Chunk::CHUNK_METADATA_SIZE as usize + chunk.length() as usize;
+            Some(chunk_offset)
+        })
+        .collect();
+    if fixes.contains(&Fix::MissingEnd) {
+        if let Some(last) = offsets.last_mut() {
+            *last = None;
+        }
+    }
+    offsets
+}
+
+// Known chunks describe themselves, and our own payloads show their header.
+fn annotate(chunk: &Chunk, color_type: Option<ColorType>) -> Option<String> {
+    if Payload::is_payload(chunk.data()) {
+        return Some(match Payload::try_from(chunk.data()) {
+            Ok(payload) => payload.to_string(),
+            Err(err) => err.to_string(),
+        });
+    }
+    match chunk.parse_known_with(color_type) {
+        Ok(KnownChunk::Unknown(_)) => None,
+        Ok(known) => Some(known.to_string()),
+        Err(err) => Some(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::ParseOptions;
+    use std::str::FromStr;
+
+    fn testing_png(path: &str) -> Png {
+        let contents = std::fs::read(path).unwrap();
+        Png::try_from(contents.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_hex_lines() {
+        let lines = hex_lines(b"IHDR\x00\x01 and some more text");
+        assert_eq!(
+            lines,
+            [
+                "  00000000  49 48 44 52 00 01 20 61  6e 64 20 73 6f 6d 65 20  |IHDR.. and some |",
+                "  00000010  6d 6f 72 65 20 74 65 78  74                       |more text|",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dump_selects_chunks() {
+        let png = testing_png("assets/catgurl.png");
+        let options = DumpOptions {
+            chunk_type: Some(ChunkType::from_str("IDAT").unwrap()),
+            index: Some(3),
+            max_bytes: Some(4),
+            annotate: true,
+        };
+        let dump = dump(&png, &[], &options).unwrap();
+        assert_eq!(dump.chunks.len(), 1);
+        let chunk = &dump.chunks[0];
+        assert_eq!(
+            (chunk.index, chunk.offset),
+            (5, Some(8 + 25 + 114 + 3 * 8204))
+        );
+        assert_eq!((chunk.data.len(), chunk.omitted), (4, 3627));
+        assert_eq!(chunk.annotation, None);
+
+        let options = DumpOptions {
+            chunk_type: Some(ChunkType::from_str("IHDR").unwrap()),
+            annotate: true,
+            ..Default::default()
+        };
+        let dump = super::dump(&png, &[], &options).unwrap();
+        assert_eq!(
+            dump.chunks[0].annotation.as_deref(),
+            Some("360x479, 8-bit indexed, non-interlaced")
+        );
+    }
+
+    #[test]
+    fn test_dump_offsets_after_damage() {
+        let mut contents = std::fs::read("assets/catgurl.png").unwrap();
+        // garbage between PLTE and the first IDAT, and IEND cut off
+        let idat = 8 + 25 + 114;
+        contents.splice(idat..idat, [0xde, 0xad, 0xbe, 0xef, 0x00]);
+        contents.truncate(contents.len() - 12);
+
+        let (png, fixes) = Png::parse(&contents, ParseOptions::lenient()).unwrap();
+        let dump = dump(&png, &fixes, &DumpOptions::default()).unwrap();
+        let offsets: Vec<_> = dump.chunks.iter().map(|chunk| chunk.offset).collect();
+        assert_eq!(
+            offsets,
+            [
+                Some(8),
+                Some(8 + 25),
+                Some(idat + 5),
+                Some(idat + 5 + 8204),
+                Some(idat + 5 + 2 * 8204),
+                Some(idat + 5 + 3 * 8204),
+                None,
+            ]
+        );
+        for chunk in &dump.chunks[..6] {
+            let offset = chunk.offset.unwrap();
+            assert_eq!(
+                contents[offset + 4..offset + 8],
+                *chunk.chunk_type.as_bytes()
+            );
+        }
+        assert!(dump
+            .lines()
+            .iter()
+            .any(|line| line.starts_with("chunk 6 not in the file: IEND")));
+    }
+}
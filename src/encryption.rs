@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::{errors::Error, keys};
+
+const WRAP_INFO: &[u8] = b"hidepng x25519 key wrap v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    pub ephemeral_public: [u8; 32],
+    pub wrapped_key: [u8; 48],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    pub nonce: [u8; 12],
+    pub recipients: Vec<WrappedKey>,
+}
+
+pub fn generate_identity() -> StaticSecret {
+    StaticSecret::random_from_rng(OsRng)
+}
+
+pub fn read_identity(path: &Path) -> anyhow::Result<StaticSecret> {
+    Ok(StaticSecret::from(keys::read_key_bytes(path)?))
+}
+
+pub fn read_recipient(path: &Path) -> anyhow::Result<PublicKey> {
+    Ok(PublicKey::from(keys::read_key_bytes(path)?))
+}
+
+// Each recipient gets the content key wrapped with a key derived from a fresh
+// ephemeral X25519 exchange, so recipients are not identifiable from the
+// stored stanzas.
+pub fn encrypt(message: &[u8], recipients: &[PublicKey]) -> (Encryption, Vec<u8>) {
+    let mut content_key = [0u8; 32];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut content_key);
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&content_key))
+        .encrypt(Nonce::from_slice(&nonce), message)
+        .expect("encrypting into a Vec cannot fail");
+
+    let recipients = recipients
+        .iter()
+        .map(|recipient| {
+            let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+            let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+            let shared = ephemeral.diffie_hellman(recipient);
+            let wrap_key = derive_wrap_key(shared.as_bytes(), &ephemeral_public, recipient);
+
+            let wrapped_key = ChaCha20Poly1305::new(&wrap_key)
+                .encrypt(&Nonce::default(), content_key.as_slice())
+                .expect("encrypting into a Vec cannot fail");
+
+            WrappedKey {
+                ephemeral_public,
+                wrapped_key: wrapped_key.try_into().unwrap(),
+            }
+        })
+        .collect();
+
+    (Encryption { nonce, recipients }, ciphertext)
+}
+
+pub fn decrypt(
+    encryption: &Encryption,
+    ciphertext: &[u8],
+    identity: &StaticSecret,
+) -> Result<Vec<u8>, Error> {
+    let public = PublicKey::from(identity);
+
+    let content_key = encryption
+        .recipients
+        .iter()
+        .find_map(|stanza| {
+            let ephemeral_public = PublicKey::from(stanza.ephemeral_public);
+            let shared = identity.diffie_hellman(&ephemeral_public);
+            let wrap_key = derive_wrap_key(shared.as_bytes(), &stanza.ephemeral_public, &public);
+
+            ChaCha20Poly1305::new(&wrap_key)
+                .decrypt(&Nonce::default(), stanza.wrapped_key.as_slice())
+                .ok()
+        })
+        .ok_or(Error::DecryptionFailed)?;
+
+    ChaCha20Poly1305::new(Key::from_slice(&content_key))
+        .decrypt(Nonce::from_slice(&encryption.nonce), ciphertext)
+        .map_err(|_| Error::DecryptionFailed)
+}
+
+fn derive_wrap_key(shared: &[u8; 32], ephemeral_public: &[u8; 32], recipient: &PublicKey) -> Key {
+    let mut salt = ephemeral_public.to_vec();
+    salt.extend(recipient.as_bytes());
+
+    let mut wrap_key = Key::default();
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(WRAP_INFO, &mut wrap_key)
+        .expect("32 bytes is a valid HKDF output length");
+    wrap_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_recipient_can_decrypt() {
+        let alice = generate_identity();
+        let bob = generate_identity();
+        let recipients = [PublicKey::from(&alice), PublicKey::from(&bob)];
+
+        let (encryption, ciphertext) = encrypt(b"for both of you", &recipients);
+        assert_eq!(encryption.recipients.len(), 2);
+
+        for identity in [alice, bob] {
+            assert_eq!(
+                decrypt(&encryption, &ciphertext, &identity).unwrap(),
+                b"for both of you"
+            );
+        }
+    }
+
+    #[test]
+    fn test_other_identity_cannot_decrypt() {
+        let recipient = generate_identity();
+        let (encryption, ciphertext) = encrypt(b"private", &[PublicKey::from(&recipient)]);
+
+        assert!(matches!(
+            decrypt(&encryption, &ciphertext, &generate_identity()),
+            Err(Error::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_ciphertext() {
+        let recipient = generate_identity();
+        let (encryption, mut ciphertext) = encrypt(b"private", &[PublicKey::from(&recipient)]);
+        ciphertext[0] ^= 1;
+
+        assert!(decrypt(&encryption, &ciphertext, &recipient).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use serde::Serialize;
+
+use crate::{
+    chunk_type::ChunkType, deniable, errors::Error, output::Report, payload::Payload, png::Png,
+};
+
+// A message stored under a chunk type, with the chunks it was joined from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub chunks: Vec<usize>,
+    pub data: Vec<u8>,
+    // None for data that isn't a hidepng payload, such as a passphrase
+    // container that the given passphrase doesn't open
+    pub payload: Option<Payload>,
+    // the payload was opened from a passphrase container, which `data` holds
+    pub sealed: bool,
+    // why a malformed payload or incomplete fragments couldn't be read
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn name(&self) -> Option<&str> {
+        self.payload.as_ref()?.name.as_deref()
+    }
+}
+
+// With a passphrase, the containers it opens are read as the payloads inside.
+pub fn entries(
+    png: &Png,
+    chunk_type: &ChunkType,
+    passphrase: Option<&str>,
+) -> Result<Vec<Entry>, Error> {
+    let indices: Vec<usize> = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type() == chunk_type)
+        .map(|(index, _)| index)
+        .collect();
+    if indices.is_empty() {
+        return Err(Error::ChunkTypeNotFound(chunk_type.to_string()));
+    }
+
+    let data = indices
+        .iter()
+        .map(|&index| png.chunks()[index].data().to_vec())
+        .collect();
+    Ok(Payload::reassemble_indexed(data)
+        .into_iter()
+        .map(|(positions, joined)| {
+            let chunks: Vec<usize> = positions
+                .iter()
+                .map(|&position| indices[position])
+                .collect();
+            let parsed = joined.and_then(|data| match Payload::is_payload(&data) {
+                true => Ok((Some(Payload::try_from(data.as_slice())?), data)),
+                false => Ok((None, data)),
+            });
+            match (parsed, passphrase) {
+                (Ok((None, data)), Some(passphrase)) => {
+                    let payload = deniable::open(&data, passphrase)
+                        .ok()
+                        .filter(|opened| Payload::is_payload(opened))
+                        .and_then(|opened| Payload::try_from(opened.as_slice()).ok());
+                    Entry {
+                        chunks,
+                        data,
+                        sealed: payload.is_some(),
+                        payload,
+                        error: None,
+                    }
+                }
+                (Ok((payload, data)), _) => Entry {
+                    chunks,
+                    data,
+                    payload,
+                    sealed: false,
+                    error: None,
+                },
+                // kept so it can be listed, and its chunks removed
+                (Err(err), _) => Entry {
+                    data: chunks
+                        .iter()
+                        .flat_map(|&index| png.chunks()[index].data().to_vec())
+                        .collect(),
+                    chunks,
+                    payload: None,
+                    sealed: false,
+                    error: Some(err.to_string()),
+                },
+            }
+        })
+        .collect())
+}
+
+pub fn find<'a>(entries: &'a [Entry], name: &str) -> Result<&'a Entry, Error> {
+    entries
+        .iter()
+        .find(|entry| entry.name() == Some(name))
+        .ok_or(Error::MessageNameNotFound(name.to_string()))
+}
+
+// Names are stored behind a one byte length.
+pub fn check_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.len() > u8::MAX as usize {
+        return Err(Error::InvalidMessageName(name.to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntrySummary {
+    pub name: Option<String>,
+    pub chunks: Vec<usize>,
+    pub size: usize,
+    pub sealed: bool,
+    pub description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    pub entries: Vec<EntrySummary>,
+}
+
+impl Report for Listing {
+    fn lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let chunks = match entry.chunks.as_slice() {
+                    [index] => format!("chunk {}", index),
+                    indices => format!(
+                        "chunks {}",
+                        indices
+                            .iter()
+                            .map(|index| index.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
+                format!(
+                    "{} ({}): {}",
+                    entry.name.as_deref().unwrap_or("unnamed"),
+                    chunks,
+                    entry.description
+                )
+            })
+            .collect()
+    }
+}
+
+pub fn list(entries: &[Entry]) -> Listing {
+    Listing {
+        entries: entries
+            .iter()
+            .map(|entry| EntrySummary {
+                name: entry.name().map(str::to_string),
+                chunks: entry.chunks.clone(),
+                size: entry.data.len(),
+                sealed: entry.sealed,
+                description: match (&entry.payload, &entry.error) {
+                    (Some(payload), _) if entry.sealed => {
+                        format!("{}, sealed with a passphrase", payload)
+                    }
+                    (Some(payload), _) => payload.to_string(),
+                    (None, Some(error)) => {
+                        format!("{} bytes that can't be read: {}", entry.data.len(), error)
+                    }
+                    (None, None) => {
+                        format!("{} bytes of unframed or sealed data", entry.data.len())
+                    }
+                },
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, png::Placement};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_entries_and_names() {
+        let contents = std::fs::read("assets/catgurl.png").unwrap();
+        let mut png = Png::try_from(contents.as_slice()).unwrap();
+        let chunk_type = ChunkType::from_str("STXT").unwrap();
+        assert!(entries(&png, &chunk_type, None).is_err());
+
+        let mut notes = Payload::new(b"remember the milk".to_vec());
+        notes.name = Some("notes".to_string());
+        for data in Payload::fragment(&notes.as_bytes(), 60).unwrap() {
+            png.insert_at(Chunk::new(chunk_type.clone(), data), Placement::BeforeEnd);
+        }
+        png.insert_at(
+            Chunk::new(chunk_type.clone(), b"sealed".to_vec()),
+            Placement::BeforeEnd,
+        );
+        let mut todo = Payload::new(b"water the plants".to_vec());
+        todo.name = Some("todo".to_string());
+        let container = deniable::seal(&[(&todo.as_bytes(), "hunter2")]).unwrap();
+        png.insert_at(
+            Chunk::new(chunk_type.clone(), container),
+            Placement::BeforeEnd,
+        );
+
+        let sealed = entries(&png, &chunk_type, Some("hunter2")).unwrap();
+        let found = find(&sealed, "todo").unwrap();
+        assert_eq!(
+            (found.chunks.as_slice(), found.sealed),
+            ([15].as_slice(), true)
+        );
+        assert!(list(&sealed).lines()[2].ends_with(", sealed with a passphrase"));
+        assert!(find(&entries(&png, &chunk_type, Some("wrong")).unwrap(), "todo").is_err());
+
+        let entries = entries(&png, &chunk_type, None).unwrap();
+        assert_eq!(entries.len(), 3);
+        let found = find(&entries, "notes").unwrap();
+        assert_eq!(found.chunks, (6..14).collect::<Vec<_>>());
+        assert_eq!(
+            found.payload.as_ref().unwrap().message,
+            b"remember the milk"
+        );
+        assert!(find(&entries, "todo").is_err());
+        assert_eq!(
+            list(&entries).lines()[1],
+            "unnamed (chunk 14): 6 bytes of unframed or sealed data"
+        );
+
+        assert!(check_name("notes").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name(&"n".repeat(256)).is_err());
+    }
+}
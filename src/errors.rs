@@ -23,6 +23,178 @@ pub enum Error {
     #[error("{0}")]
     InvalidFile(&'static str),
 
+    #[error("Chunk `{chunk_type}` number {index} does not exist, the file has {count}.")]
+    ChunkIndexNotFound {
+        chunk_type: String,
+        index: usize,
+        count: usize,
+    },
+
     #[error("File Signature does not match standard PNG signature. Possible corruption.")]
     InvalidPngSignature,
+
+    #[error("Malformed `{chunk_type}` chunk: {reason}.")]
+    MalformedChunk {
+        chunk_type: &'static str,
+        reason: &'static str,
+    },
+
+    #[error("Invalid animation: {0}.")]
+    InvalidAnimation(String),
+
+    #[error("Message needs {needed} bytes but only {available} bytes fit.")]
+    CapacityExceeded { needed: usize, available: usize },
+
+    #[error("No hidden data found.")]
+    NoHiddenData,
+
+    #[error("Malformed payload: {0}.")]
+    MalformedPayload(&'static str),
+
+    #[error("Payload version {0} is not supported by this version of hidepng.")]
+    UnsupportedPayloadVersion(u8),
+
+    #[error("Payload is corrupted, its hash does not match its contents.")]
+    CorruptPayload,
+
+    #[error("`{0}` is not a valid key file.")]
+    InvalidKey(String),
+
+    #[error("Key file `{0}` already exists, pass --force to overwrite it.")]
+    KeyFileExists(String),
+
+    #[error("Payload is not signed.")]
+    UnsignedPayload,
+
+    #[error("Signature verification failed, the payload was modified or forged.")]
+    InvalidSignature,
+
+    #[error("Payload was signed by an untrusted key `{0}`.")]
+    UntrustedSigner(String),
+
+    #[error("Critical chunks changed since the payload was signed.")]
+    CriticalChunksModified,
+
+    #[error("Image content changed since the payload was embedded.")]
+    ImageContentModified,
+
+    #[error("At most 255 recipients are supported, got {0}.")]
+    TooManyRecipients(usize),
+
+    #[error("Payload is encrypted, an identity key is needed to read it.")]
+    EncryptedPayload,
+
+    #[error("Decryption failed, the payload is not addressed to this identity or was modified.")]
+    DecryptionFailed,
+
+    #[error("Invalid decoy messages: {0}.")]
+    InvalidDecoys(String),
+
+    #[error("{0}.")]
+    UnsupportedImage(&'static str),
+
+    #[error("{0}.")]
+    IncompatibleOptions(&'static str),
+
+    #[error("Invalid config file `{path}`: {reason}.")]
+    InvalidConfig { path: String, reason: String },
+
+    #[error("Profile `{0}` is not defined in any config file.")]
+    UnknownProfile(String),
+
+    #[error("Invalid manifest: {0}.")]
+    InvalidManifest(String),
+
+    #[error("No message named `{0}` was found.")]
+    MessageNameNotFound(String),
+
+    #[error("A message named `{0}` already exists, use `update` to replace it.")]
+    DuplicateMessageName(String),
+
+    #[error("Message name `{0}` must be 1 to 255 bytes long.")]
+    InvalidMessageName(String),
+}
+
+impl Error {
+    // Stable identifier for machine-readable output, part of the JSON schema.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidSize { .. } => "invalid_size",
+            Error::InputTooSmall => "input_too_small",
+            Error::InvalidCharacterSet(_) => "invalid_character_set",
+            Error::InvalidReservebit(_) => "invalid_reserved_bit",
+            Error::CrcMismatch => "crc_mismatch",
+            Error::ChunkTypeNotFound(_) => "chunk_type_not_found",
+            Error::ChunkIndexNotFound { .. } => "chunk_index_not_found",
+            Error::InvalidFile(_) => "invalid_file",
+            Error::InvalidPngSignature => "invalid_png_signature",
+            Error::MalformedChunk { .. } => "malformed_chunk",
+            Error::InvalidAnimation(_) => "invalid_animation",
+            Error::CapacityExceeded { .. } => "capacity_exceeded",
+            Error::NoHiddenData => "no_hidden_data",
+            Error::MalformedPayload(_) => "malformed_payload",
+            Error::UnsupportedPayloadVersion(_) => "unsupported_payload_version",
+            Error::CorruptPayload => "corrupt_payload",
+            Error::InvalidKey(_) => "invalid_key",
+            Error::KeyFileExists(_) => "key_file_exists",
+            Error::UnsignedPayload => "unsigned_payload",
+            Error::InvalidSignature => "invalid_signature",
+            Error::UntrustedSigner(_) => "untrusted_signer",
+            Error::CriticalChunksModified => "critical_chunks_modified",
+            Error::ImageContentModified => "image_content_modified",
+            Error::TooManyRecipients(_) => "too_many_recipients",
+            Error::EncryptedPayload => "encrypted_payload",
+            Error::DecryptionFailed => "decryption_failed",
+            Error::InvalidDecoys(_) => "invalid_decoys",
+            Error::UnsupportedImage(_) => "unsupported_image",
+            Error::IncompatibleOptions(_) => "incompatible_options",
+            Error::InvalidConfig { .. } => "invalid_config",
+            Error::UnknownProfile(_) => "unknown_profile",
+            Error::InvalidManifest(_) => "invalid_manifest",
+            Error::MessageNameNotFound(_) => "message_name_not_found",
+            Error::DuplicateMessageName(_) => "duplicate_message_name",
+            Error::InvalidMessageName(_) => "invalid_message_name",
+        }
+    }
+
+    // Process exit status for this class of failure, documented in the README.
+    // 2 is left to clap for usage errors and 3 is used for I/O errors.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::ChunkTypeNotFound(_)
+            | Error::ChunkIndexNotFound { .. }
+            | Error::NoHiddenData
+            | Error::MessageNameNotFound(_) => 4,
+            Error::InvalidSize { .. }
+            | Error::InputTooSmall
+            | Error::InvalidCharacterSet(_)
+            | Error::InvalidReservebit(_)
+            | Error::InvalidFile(_)
+            | Error::InvalidPngSignature
+            | Error::MalformedChunk { .. }
+            | Error::InvalidAnimation(_) => 5,
+            Error::CrcMismatch => 6,
+            Error::EncryptedPayload | Error::DecryptionFailed => 7,
+            Error::CapacityExceeded { .. } => 8,
+            Error::UnsignedPayload
+            | Error::InvalidSignature
+            | Error::UntrustedSigner(_)
+            | Error::CriticalChunksModified
+            | Error::ImageContentModified => 9,
+            Error::MalformedPayload(_)
+            | Error::UnsupportedPayloadVersion(_)
+            | Error::CorruptPayload => 10,
+            Error::InvalidKey(_)
+            | Error::KeyFileExists(_)
+            | Error::TooManyRecipients(_)
+            | Error::InvalidDecoys(_)
+            | Error::UnsupportedImage(_)
+            | Error::IncompatibleOptions(_)
+            | Error::InvalidConfig { .. }
+            | Error::UnknownProfile(_)
+            | Error::InvalidManifest(_)
+            | Error::DuplicateMessageName(_)
+            | Error::InvalidMessageName(_) => 1,
+        }
+    }
 }
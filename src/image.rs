@@ -0,0 +1,849 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use serde::{Serialize, Serializer};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    errors::Error,
+    known_chunk::{ColorType, ImageHeader},
+    png::Png,
+};
+
+// (x offset, y offset, x step, y step) of each Adam7 pass.
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+#[derive(Debug, Clone)]
+pub struct RawImage {
+    pub header: ImageHeader,
+    pub data: Vec<u8>,
+}
+
+impl RawImage {
+    pub fn from_png(png: &Png) -> Result<Self, Error> {
+        let header = png.image_header()?;
+        if header.width == 0 || header.height == 0 {
+            return Err(Error::InvalidFile("Image has no pixels"));
+        }
+
+        let filtered = inflate(&header, &image_data(png)?)?;
+
+        let data = if header.interlace_method == 1 {
+            deinterlace(&header, &filtered)?
+        } else {
+            unfilter(
+                &filtered,
+                row_bytes(&header, header.width as usize),
+                header.height as usize,
+                bytes_per_pixel(&header),
+            )?
+        };
+
+        Ok(RawImage { header, data })
+    }
+
+    // Filters and compresses the pixels again, keeping the interlace method.
+    pub fn encode_with(&self, filters: RowFilters, compression: Compression) -> Vec<u8> {
+        let header = &self.header;
+        let (width, height) = (header.width as usize, header.height as usize);
+        let stride = row_bytes(header, width);
+        let mut candidates = (0..).map(|row| match filters {
+            RowFilters::Best(filter_types) => filter_types,
+            RowFilters::Fixed(filter_types) => filter_types.get(row..=row).unwrap_or(&[0]),
+        });
+
+        let filtered = if header.interlace_method == 1 {
+            let bits = bits_per_pixel(header);
+            let mut filtered = Vec::new();
+            for (x0, y0, dx, dy) in ADAM7_PASSES {
+                if x0 >= width || y0 >= height {
+                    continue;
+                }
+                let pass_width = (width - x0).div_ceil(dx);
+                let pass_stride = row_bytes(header, pass_width);
+                let mut pass = Vec::new();
+                for y in (y0..height).step_by(dy) {
+                    let mut row = vec![0u8; pass_stride];
+                    for (px, x) in (x0..width).step_by(dx).enumerate() {
+                        copy_pixel(&self.data[y * stride..], x, &mut row, px, bits);
+                    }
+                    pass.extend(row);
+                }
+                filtered.extend(filter(header, &pass, pass_stride, &mut candidates));
+            }
+            filtered
+        } else {
+            filter(header, &self.data, stride, &mut candidates)
+        };
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+        encoder.write_all(&filtered).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // Replaces the image data in `png`, imitating the fingerprint of the data
+    // being replaced.
+    pub fn write_to(&self, png: &mut Png) -> Result<Preservation, Error> {
+        let fingerprint = Fingerprint::from_png(png)?;
+        let data = self.encode_with(
+            RowFilters::Fixed(&fingerprint.row_filters),
+            fingerprint.compression(),
+        );
+        let preservation = Preservation {
+            idat_split: fingerprint.has_regular_split(),
+            zlib_header: data.starts_with(&fingerprint.zlib_header),
+            row_filters: true,
+        };
+
+        let idat_type = ChunkType::try_from(*b"IDAT").unwrap();
+        let idats = fingerprint
+            .split(data)
+            .into_iter()
+            .map(|data| Chunk::new(idat_type.clone(), data))
+            .collect();
+        png.replace_image_data(idats)?;
+        Ok(preservation)
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.header.width as usize * self.header.height as usize * channels(self.header.color_type)
+    }
+
+    // Samples are numbered in row-major order, channel by channel. 16-bit
+    // samples are returned whole.
+    pub fn sample(&self, index: usize) -> u16 {
+        let (byte, shift) = self.sample_position(index);
+        match self.header.bit_depth {
+            16 => u16::from_be_bytes([self.data[byte], self.data[byte + 1]]),
+            8 => self.data[byte] as u16,
+            depth => ((self.data[byte] >> shift) & ((1 << depth) - 1)) as u16,
+        }
+    }
+
+    pub fn set_sample(&mut self, index: usize, value: u16) {
+        let (byte, shift) = self.sample_position(index);
+        match self.header.bit_depth {
+            16 => self.data[byte..byte + 2].copy_from_slice(&value.to_be_bytes()),
+            8 => self.data[byte] = value as u8,
+            depth => {
+                let mask = ((1u8 << depth) - 1) << shift;
+                self.data[byte] = (self.data[byte] & !mask) | (((value as u8) << shift) & mask);
+            }
+        }
+    }
+
+    fn sample_position(&self, index: usize) -> (usize, usize) {
+        let header = &self.header;
+        let samples_per_row = header.width as usize * channels(header.color_type);
+        let (row, column) = (index / samples_per_row, index % samples_per_row);
+        let bit = column * header.bit_depth as usize;
+        let byte = row * row_bytes(header, header.width as usize) + bit / 8;
+        let shift = 8usize.saturating_sub(header.bit_depth as usize + bit % 8);
+        (byte, shift)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RowFilters<'a> {
+    // each row takes whichever of these gives the smallest sum of absolute
+    // differences, the heuristic libpng uses
+    Best(&'a [u8]),
+    // one filter type per stored row, Adam7 passes in order
+    Fixed(&'a [u8]),
+}
+
+// The traces an encoder leaves in the image data besides the pixels, which a
+// rewrite should keep so the file doesn't look re-encoded.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fingerprint {
+    pub idat_sizes: Vec<usize>,
+    #[serde(serialize_with = "as_hex")]
+    pub zlib_header: [u8; 2],
+    pub row_filters: Vec<u8>,
+}
+
+fn as_hex<S: Serializer>(bytes: &[u8; 2], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(bytes))
+}
+
+impl Fingerprint {
+    pub fn from_png(png: &Png) -> Result<Self, Error> {
+        let header = png.image_header()?;
+        let idat_sizes = png
+            .chunk_by_type("IDAT")
+            .ok_or(Error::ChunkTypeNotFound("IDAT".to_string()))?
+            .iter()
+            .map(|chunk| chunk.length() as usize)
+            .collect();
+        let compressed = image_data(png)?;
+        let filtered = inflate(&header, &compressed)?;
+        if filtered_size(&header).is_none_or(|size| filtered.len() < size) {
+            return Err(Error::InvalidFile("IDAT data is shorter than the image"));
+        }
+
+        let mut row_filters = Vec::new();
+        let mut offset = 0;
+        for length in row_lengths(&header) {
+            let filter_type = filtered
+                .get(offset)
+                .ok_or(Error::InvalidFile("IDAT data is shorter than the image"))?;
+            row_filters.push(*filter_type);
+            offset += length + 1;
+        }
+
+        Ok(Fingerprint {
+            idat_sizes,
+            zlib_header: [compressed[0], compressed[1]],
+            row_filters,
+        })
+    }
+
+    // The level zlib encoders note in the header's FLEVEL bits.
+    pub fn compression(&self) -> Compression {
+        match self.zlib_header[1] >> 6 {
+            0 => Compression::new(1),
+            1 => Compression::new(2),
+            2 => Compression::default(),
+            _ => Compression::best(),
+        }
+    }
+
+    // Encoders normally write equally sized IDATs and put the rest in a
+    // shorter last one.
+    pub fn has_regular_split(&self) -> bool {
+        let (last, rest) = self.idat_sizes.split_last().unwrap_or((&0, &[]));
+        rest.iter().all(|&size| size == self.idat_sizes[0]) && *last <= self.idat_sizes[0]
+    }
+
+    // Cuts `data` at the same sizes as the original. A regular split keeps its
+    // chunk size, otherwise the original sizes are used in order and the last
+    // chunk takes whatever is left.
+    pub fn split(&self, data: Vec<u8>) -> Vec<Vec<u8>> {
+        if self.has_regular_split() && self.idat_sizes[0] > 0 {
+            return data
+                .chunks(self.idat_sizes[0])
+                .map(|chunk| chunk.to_vec())
+                .collect();
+        }
+
+        let mut chunks = Vec::new();
+        let mut rest = data.as_slice();
+        for &size in &self.idat_sizes[..self.idat_sizes.len().saturating_sub(1)] {
+            if rest.len() <= size {
+                break;
+            }
+            let (chunk, remainder) = rest.split_at(size);
+            chunks.push(chunk.to_vec());
+            rest = remainder;
+        }
+        chunks.push(rest.to_vec());
+        chunks
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "zlib header {}, {} IDAT chunks of {} bytes, row filters",
+            hex::encode(self.zlib_header),
+            self.idat_sizes.len(),
+            self.idat_sizes
+                .iter()
+                .map(|size| size.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+        for (filter_type, name) in FILTER_NAMES.iter().enumerate() {
+            let count = self
+                .row_filters
+                .iter()
+                .filter(|&&row_filter| row_filter as usize == filter_type)
+                .count();
+            if count > 0 {
+                write!(f, " {} {}", name, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Which parts of the fingerprint a rewrite managed to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preservation {
+    pub idat_split: bool,
+    pub zlib_header: bool,
+    pub row_filters: bool,
+}
+
+impl Display for Preservation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let properties = [
+            ("IDAT split", self.idat_split),
+            ("zlib header", self.zlib_header),
+            ("row filters", self.row_filters),
+        ];
+        let list = |kept: bool| {
+            properties
+                .iter()
+                .filter(|(_, preserved)| *preserved == kept)
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (list(true), list(false)) {
+            (kept, changed) if changed.is_empty() => write!(f, "kept {}", kept),
+            (kept, changed) if kept.is_empty() => write!(f, "changed {}", changed),
+            (kept, changed) => write!(f, "kept {}, changed {}", kept, changed),
+        }
+    }
+}
+
+const FILTER_NAMES: [&str; 5] = ["none", "sub", "up", "average", "paeth"];
+
+pub fn channels(color_type: ColorType) -> usize {
+    match color_type {
+        ColorType::Grayscale | ColorType::Indexed => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+    }
+}
+
+fn bits_per_pixel(header: &ImageHeader) -> usize {
+    channels(header.color_type) * header.bit_depth as usize
+}
+
+// Filters operate on whole bytes, so sub-byte pixels count as one byte.
+fn bytes_per_pixel(header: &ImageHeader) -> usize {
+    bits_per_pixel(header).div_ceil(8)
+}
+
+fn row_bytes(header: &ImageHeader, width: usize) -> usize {
+    (width * bits_per_pixel(header)).div_ceil(8)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn unfilter(filtered: &[u8], stride: usize, height: usize, bpp: usize) -> Result<Vec<u8>, Error> {
+    match (stride + 1).checked_mul(height) {
+        Some(size) if filtered.len() >= size => {}
+        _ => return Err(Error::InvalidFile("IDAT data is shorter than the image")),
+    }
+
+    let mut output = vec![0u8; stride * height];
+    for row in 0..height {
+        let line = &filtered[row * (stride + 1)..(row + 1) * (stride + 1)];
+        let (filter, line) = (line[0], &line[1..]);
+
+        for x in 0..stride {
+            let left = if x >= bpp {
+                output[row * stride + x - bpp]
+            } else {
+                0
+            };
+            let up = if row > 0 {
+                output[(row - 1) * stride + x]
+            } else {
+                0
+            };
+            let up_left = if row > 0 && x >= bpp {
+                output[(row - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(Error::InvalidFile("Unknown scanline filter type")),
+            };
+            output[row * stride + x] = line[x].wrapping_add(predictor);
+        }
+    }
+
+    Ok(output)
+}
+
+// Filters each row with the best of the filter types `candidates` offers it.
+fn filter<'a>(
+    header: &ImageHeader,
+    raw: &[u8],
+    stride: usize,
+    candidates: &mut impl Iterator<Item = &'a [u8]>,
+) -> Vec<u8> {
+    let bpp = bytes_per_pixel(header);
+
+    let mut output = Vec::with_capacity(raw.len() + raw.len() / stride.max(1));
+    for (index, row) in raw.chunks(stride).enumerate() {
+        let previous = index
+            .checked_sub(1)
+            .map(|previous| &raw[previous * stride..index * stride]);
+        let best = candidates
+            .next()
+            .unwrap()
+            .iter()
+            .map(|&filter_type| filter_row(filter_type, row, previous, bpp))
+            .min_by_key(|line| {
+                line[1..]
+                    .iter()
+                    .map(|&byte| (byte as i8).unsigned_abs() as u32)
+                    .sum::<u32>()
+            })
+            .unwrap();
+        output.extend(best);
+    }
+    output
+}
+
+fn filter_row(filter_type: u8, row: &[u8], previous: Option<&[u8]>, bpp: usize) -> Vec<u8> {
+    let mut line = Vec::with_capacity(row.len() + 1);
+    line.push(filter_type);
+    for x in 0..row.len() {
+        let left = if x >= bpp { row[x - bpp] } else { 0 };
+        let up = previous.map_or(0, |previous| previous[x]);
+        let up_left = match previous {
+            Some(previous) if x >= bpp => previous[x - bpp],
+            _ => 0,
+        };
+
+        let predictor = match filter_type {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        line.push(row[x].wrapping_sub(predictor));
+    }
+    line
+}
+
+fn image_data(png: &Png) -> Result<Vec<u8>, Error> {
+    let compressed: Vec<u8> = png
+        .chunk_by_type("IDAT")
+        .ok_or(Error::ChunkTypeNotFound("IDAT".to_string()))?
+        .iter()
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.len() < 2 {
+        return Err(Error::InvalidFile("IDAT data is not a valid zlib stream"));
+    }
+    Ok(compressed)
+}
+
+// Stops at the size the header calls for, so a small IDAT cannot inflate
+// without bound.
+fn inflate(header: &ImageHeader, compressed: &[u8]) -> Result<Vec<u8>, Error> {
+    let limit = filtered_size(header).ok_or(Error::InvalidFile("Image is too large"))?;
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(limit as u64)
+        .read_to_end(&mut filtered)
+        .map_err(|_| Error::InvalidFile("IDAT data is not a valid zlib stream"))?;
+    Ok(filtered)
+}
+
+// Length of every stored row without its filter byte, Adam7 passes in order.
+fn row_lengths(header: &ImageHeader) -> Vec<usize> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    if header.interlace_method != 1 {
+        return vec![row_bytes(header, width); height];
+    }
+
+    let mut lengths = Vec::new();
+    for (x0, y0, dx, dy) in ADAM7_PASSES {
+        if x0 >= width || y0 >= height {
+            continue;
+        }
+        let pass_width = (width - x0).div_ceil(dx);
+        let pass_height = (height - y0).div_ceil(dy);
+        lengths.extend(vec![row_bytes(header, pass_width); pass_height]);
+    }
+    lengths
+}
+
+// Filtered bytes the header calls for, or None if that overflows.
+fn filtered_size(header: &ImageHeader) -> Option<usize> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    if header.interlace_method != 1 {
+        return (row_bytes(header, width) + 1).checked_mul(height);
+    }
+
+    ADAM7_PASSES
+        .iter()
+        .filter(|&&(x0, y0, _, _)| x0 < width && y0 < height)
+        .try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+            let pass_width = (width - x0).div_ceil(dx);
+            let pass_height = (height - y0).div_ceil(dy);
+            (row_bytes(header, pass_width) + 1)
+                .checked_mul(pass_height)?
+                .checked_add(total)
+        })
+}
+
+fn deinterlace(header: &ImageHeader, filtered: &[u8]) -> Result<Vec<u8>, Error> {
+    let width = header.width as usize;
+    let height = header.height as usize;
+    let bits = bits_per_pixel(header);
+    let stride = row_bytes(header, width);
+    // the header is untrusted, so check the data is there before allocating
+    let size = match (filtered_size(header), stride.checked_mul(height)) {
+        (Some(filtered_size), Some(size)) if filtered.len() >= filtered_size => size,
+        _ => return Err(Error::InvalidFile("IDAT data is shorter than the image")),
+    };
+    let mut output = vec![0u8; size];
+    let mut offset = 0;
+
+    for (x0, y0, dx, dy) in ADAM7_PASSES {
+        if x0 >= width || y0 >= height {
+            continue;
+        }
+        let pass_width = (width - x0).div_ceil(dx);
+        let pass_height = (height - y0).div_ceil(dy);
+        let pass_stride = row_bytes(header, pass_width);
+        let pass_size = (pass_stride + 1) * pass_height;
+
+        let pass = unfilter(
+            filtered.get(offset..).unwrap_or_default(),
+            pass_stride,
+            pass_height,
+            bytes_per_pixel(header),
+        )?;
+        offset += pass_size;
+
+        for py in 0..pass_height {
+            for px in 0..pass_width {
+                let (x, y) = (x0 + px * dx, y0 + py * dy);
+                copy_pixel(
+                    &pass[py * pass_stride..],
+                    px,
+                    &mut output[y * stride..],
+                    x,
+                    bits,
+                );
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn copy_pixel(source: &[u8], source_x: usize, target: &mut [u8], target_x: usize, bits: usize) {
+    if bits >= 8 {
+        let size = bits / 8;
+        target[target_x * size..(target_x + 1) * size]
+            .copy_from_slice(&source[source_x * size..(source_x + 1) * size]);
+        return;
+    }
+
+    let mask = (1u8 << bits) - 1;
+    let source_shift = 8 - bits - (source_x * bits) % 8;
+    let value = (source[source_x * bits / 8] >> source_shift) & mask;
+
+    let target_shift = 8 - bits - (target_x * bits) % 8;
+    let byte = &mut target[target_x * bits / 8];
+    *byte = (*byte & !(mask << target_shift)) | (value << target_shift);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, known_chunk::KnownChunk};
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::{io::Write, str::FromStr};
+
+    fn interlaced_png(header: &ImageHeader, pixels: &[u8]) -> Png {
+        let bits = bits_per_pixel(header);
+        let stride = row_bytes(header, header.width as usize);
+        let mut filtered = Vec::new();
+
+        for (x0, y0, dx, dy) in ADAM7_PASSES {
+            let (width, height) = (header.width as usize, header.height as usize);
+            if x0 >= width || y0 >= height {
+                continue;
+            }
+            let pass_width = (width - x0).div_ceil(dx);
+            let pass_stride = row_bytes(header, pass_width);
+            for y in (y0..height).step_by(dy) {
+                let mut row = vec![0u8; pass_stride];
+                for (px, x) in (x0..width).step_by(dx).enumerate() {
+                    copy_pixel(&pixels[y * stride..], x, &mut row, px, bits);
+                }
+                filtered.push(0);
+                filtered.extend(row);
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered).unwrap();
+        let idat = Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            encoder.finish().unwrap(),
+        );
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        Png::from_chunks(vec![
+            KnownChunk::Ihdr(header.clone()).to_chunk(),
+            idat,
+            iend,
+        ])
+    }
+
+    #[test]
+    fn test_deinterlace() {
+        for (bit_depth, color_type) in [(8, ColorType::Rgb), (2, ColorType::Grayscale)] {
+            let header = ImageHeader {
+                width: 13,
+                height: 9,
+                bit_depth,
+                color_type,
+                compression_method: 0,
+                filter_method: 0,
+                interlace_method: 1,
+            };
+            let size = row_bytes(&header, 13) * 9;
+            let mut pixels: Vec<u8> = (0..size).map(|i| (i * 37 % 251) as u8).collect();
+            // clear the padding bits at the end of each row
+            let stride = row_bytes(&header, 13);
+            let used_bits = 13 * bits_per_pixel(&header);
+            if !used_bits.is_multiple_of(8) {
+                for row in pixels.chunks_mut(stride) {
+                    row[stride - 1] &= !(0xff >> (used_bits % 8));
+                }
+            }
+
+            let image = RawImage::from_png(&interlaced_png(&header, &pixels)).unwrap();
+            assert_eq!(image.data, pixels);
+        }
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        assert!(unfilter(&[0; 8], usize::MAX / 2, 3, 1).is_err());
+
+        for interlace_method in [0, 1] {
+            let header = ImageHeader {
+                width: u32::MAX,
+                height: u32::MAX,
+                bit_depth: 16,
+                color_type: ColorType::Rgba,
+                compression_method: 0,
+                filter_method: 0,
+                interlace_method,
+            };
+            let tiny = ImageHeader {
+                width: 1,
+                height: 1,
+                ..header.clone()
+            };
+            let mut png = interlaced_png(&tiny, &[0; 8]);
+            png.chunks_mut()[0] = KnownChunk::Ihdr(header.clone()).to_chunk();
+
+            assert!(deinterlace(&header, &[0; 64]).is_err());
+            assert!(RawImage::from_png(&png).is_err());
+            assert!(Fingerprint::from_png(&png).is_err());
+        }
+    }
+
+    #[test]
+    fn test_bit_depth_not_allowed_for_color_type() {
+        let header = ImageHeader {
+            width: 1,
+            height: 1,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        };
+        let mut png = interlaced_png(&header, &[0; 3]);
+        let twelve_bit = ImageHeader {
+            bit_depth: 12,
+            ..header
+        };
+        png.chunks_mut()[0] = KnownChunk::Ihdr(twelve_bit).to_chunk();
+
+        assert!(matches!(
+            RawImage::from_png(&png),
+            Err(Error::MalformedChunk {
+                chunk_type: "IHDR",
+                ..
+            })
+        ));
+        assert!(Fingerprint::from_png(&png).is_err());
+    }
+
+    const PNG_FILES: [&str; 3] = [
+        "assets/catgurl.png",
+        "assets/animegurl.png",
+        "assets/ryanGosling.png",
+    ];
+
+    #[test]
+    fn test_decode_assets() {
+        for path in PNG_FILES {
+            let contents = std::fs::read(path).unwrap();
+            let png = Png::try_from(contents.as_slice()).unwrap();
+            let image = RawImage::from_png(&png).unwrap();
+            assert_eq!(
+                image.data.len(),
+                row_bytes(&image.header, image.header.width as usize)
+                    * image.header.height as usize
+            );
+        }
+    }
+
+    #[test]
+    fn test_unfilter_all_filter_types() {
+        // 2x5 grayscale image, one row per filter type.
+        #[rustfmt::skip]
+        let filtered = [
+            0, 10, 20,
+            1, 10, 5,
+            2, 1, 1,
+            3, 6, 10,
+            4, 1, 1,
+        ];
+        let output = unfilter(&filtered, 2, 5, 1).unwrap();
+        assert_eq!(output, [10, 20, 10, 15, 11, 16, 11, 23, 12, 24]);
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        for path in PNG_FILES {
+            let contents = std::fs::read(path).unwrap();
+            let mut png = Png::try_from(contents.as_slice()).unwrap();
+            let image = RawImage::from_png(&png).unwrap();
+
+            image.write_to(&mut png).unwrap();
+            assert_eq!(RawImage::from_png(&png).unwrap().data, image.data);
+        }
+    }
+
+    #[test]
+    fn test_write_keeps_fingerprint() {
+        for path in PNG_FILES {
+            let contents = std::fs::read(path).unwrap();
+            let mut png = Png::try_from(contents.as_slice()).unwrap();
+            let original = Fingerprint::from_png(&png).unwrap();
+            let mut image = RawImage::from_png(&png).unwrap();
+            image.set_sample(0, image.sample(0) ^ 1);
+
+            let preservation = image.write_to(&mut png).unwrap();
+            let rewritten = Fingerprint::from_png(&png).unwrap();
+            assert!(preservation.zlib_header && preservation.row_filters);
+            assert_eq!(rewritten.zlib_header, original.zlib_header);
+            assert_eq!(rewritten.row_filters, original.row_filters);
+            let sizes = &rewritten.idat_sizes;
+            assert!(sizes[..sizes.len() - 1]
+                .iter()
+                .all(|&size| size == original.idat_sizes[0]));
+        }
+    }
+
+    #[test]
+    fn test_irregular_split() {
+        let fingerprint = Fingerprint {
+            idat_sizes: vec![3, 5, 2],
+            zlib_header: [0x78, 0x9c],
+            row_filters: Vec::new(),
+        };
+        assert!(!fingerprint.has_regular_split());
+        let sizes = |length: usize| -> Vec<usize> {
+            fingerprint
+                .split(vec![0; length])
+                .iter()
+                .map(Vec::len)
+                .collect()
+        };
+        assert_eq!(sizes(10), [3, 5, 2]);
+        assert_eq!(sizes(12), [3, 5, 4]);
+        assert_eq!(sizes(6), [3, 3]);
+    }
+
+    #[test]
+    fn test_encode_interlaced_round_trip() {
+        let header = ImageHeader {
+            width: 11,
+            height: 7,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 1,
+        };
+        let pixels: Vec<u8> = (0..11 * 7 * 4).map(|i| (i * 13 % 256) as u8).collect();
+        let mut png = interlaced_png(&header, &pixels);
+        let image = RawImage::from_png(&png).unwrap();
+
+        image.write_to(&mut png).unwrap();
+        assert_eq!(RawImage::from_png(&png).unwrap().data, pixels);
+    }
+
+    #[test]
+    fn test_samples() {
+        let mut image = RawImage {
+            header: ImageHeader {
+                width: 3,
+                height: 2,
+                bit_depth: 2,
+                color_type: ColorType::Grayscale,
+                compression_method: 0,
+                filter_method: 0,
+                interlace_method: 0,
+            },
+            data: vec![0b0110_1100, 0b1110_0100],
+        };
+        assert_eq!(image.sample_count(), 6);
+        assert_eq!(
+            (0..6).map(|i| image.sample(i)).collect::<Vec<_>>(),
+            [1, 2, 3, 3, 2, 1]
+        );
+
+        image.set_sample(4, 0);
+        assert_eq!(image.data, [0b0110_1100, 0b1100_0100]);
+
+        image.header.bit_depth = 16;
+        image.header.width = 1;
+        image.data = vec![0x12, 0x34, 0x56, 0x78];
+        assert_eq!(image.sample(1), 0x5678);
+        image.set_sample(0, 0xabcd);
+        assert_eq!(image.data, [0xab, 0xcd, 0x56, 0x78]);
+    }
+
+    #[test]
+    fn test_sub_byte_copy() {
+        let source = [0b1011_0000];
+        let mut target = [0u8; 1];
+        copy_pixel(&source, 1, &mut target, 3, 2);
+        assert_eq!(target, [0b0000_0011]);
+    }
+}
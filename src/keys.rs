@@ -0,0 +1,122 @@
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::Error;
+
+// Existing keys are only replaced with `force`, so an identity isn't lost by accident.
+pub fn write_key_pair(
+    path: &Path,
+    secret: [u8; 32],
+    public: [u8; 32],
+    force: bool,
+) -> anyhow::Result<()> {
+    let public_path = public_key_path(path);
+    for path in [path, public_path.as_path()] {
+        if path.exists() {
+            if !force {
+                return Err(Error::KeyFileExists(path.display().to_string()).into());
+            }
+            fs::remove_file(path)?;
+        }
+    }
+
+    write_key(path, secret, 0o600)?;
+    write_key(&public_path, public, 0o644)?;
+    Ok(())
+}
+
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn write_key(path: &Path, key: [u8; 32], mode: u32) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(mode);
+    writeln!(options.open(path)?, "{}", hex::encode(key))
+}
+
+pub fn public_key_path(path: &Path) -> PathBuf {
+    let mut public = path.as_os_str().to_owned();
+    public.push(".pub");
+    public.into()
+}
+
+// Keys are stored as hex text, but raw 32 byte files are accepted as well.
+pub fn read_key_bytes(path: &Path) -> anyhow::Result<[u8; 32]> {
+    let contents = fs::read(path)?;
+    let bytes = match String::from_utf8(contents.clone()) {
+        Ok(text) if contents.len() != 32 => {
+            hex::decode(text.trim()).map_err(|_| Error::InvalidKey(path.display().to_string()))?
+        }
+        _ => contents,
+    };
+
+    Ok(bytes
+        .try_into()
+        .map_err(|_| Error::InvalidKey(path.display().to_string()))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_pair_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        write_key_pair(&path, [1; 32], [2; 32], false).unwrap();
+
+        assert_eq!(read_key_bytes(&path).unwrap(), [1; 32]);
+        assert_eq!(read_key_bytes(&public_key_path(&path)).unwrap(), [2; 32]);
+    }
+
+    #[test]
+    fn test_key_pair_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        write_key_pair(&path, [1; 32], [2; 32], false).unwrap();
+
+        let err = write_key_pair(&path, [3; 32], [4; 32], false).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::KeyFileExists(_))));
+        assert_eq!(read_key_bytes(&path).unwrap(), [1; 32]);
+
+        write_key_pair(&path, [3; 32], [4; 32], true).unwrap();
+        assert_eq!(read_key_bytes(&path).unwrap(), [3; 32]);
+        assert_eq!(read_key_bytes(&public_key_path(&path)).unwrap(), [4; 32]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_secret_key_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        write_key_pair(&path, [1; 32], [2; 32], false).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_raw_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw.key");
+        fs::write(&path, [7; 32]).unwrap();
+
+        assert_eq!(read_key_bytes(&path).unwrap(), [7; 32]);
+    }
+
+    #[test]
+    fn test_invalid_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.key");
+        fs::write(&path, "not a key").unwrap();
+
+        assert!(read_key_bytes(&path).is_err());
+    }
+}
@@ -0,0 +1,965 @@
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, errors::Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    // Bit depths the PNG specification allows for each colour type.
+    pub fn bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(Error::MalformedChunk {
+                chunk_type: "IHDR",
+                reason: "unknown colour type",
+            }),
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "RGB",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "RGBA",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    Gray(u16),
+    Rgb(u16, u16, u16),
+    Palette(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Background {
+    PaletteIndex(u8),
+    Gray(u16),
+    Rgb(u16, u16, u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white_x: u32,
+    pub white_y: u32,
+    pub red_x: u32,
+    pub red_y: u32,
+    pub green_x: u32,
+    pub green_y: u32,
+    pub blue_x: u32,
+    pub blue_y: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuggestedPaletteEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    pub frequency: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: u8,
+    pub blend_op: u8,
+}
+
+impl FrameControl {
+    pub fn delay_seconds(&self) -> f64 {
+        // a zero denominator means the numerator is in hundredths of a second
+        let den = if self.delay_den == 0 {
+            100
+        } else {
+            self.delay_den
+        };
+        self.delay_num as f64 / den as f64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum KnownChunk {
+    Ihdr(ImageHeader),
+    Plte(Vec<PaletteEntry>),
+    Trns(Transparency),
+    Gama(u32),
+    Chrm(Chromaticities),
+    Srgb(u8),
+    Iccp {
+        name: String,
+        profile: Vec<u8>,
+    },
+    Sbit(Vec<u8>),
+    Bkgd(Background),
+    Hist(Vec<u16>),
+    Phys {
+        x: u32,
+        y: u32,
+        unit: u8,
+    },
+    Splt {
+        name: String,
+        sample_depth: u8,
+        entries: Vec<SuggestedPaletteEntry>,
+    },
+    Time(Timestamp),
+    Exif(Vec<u8>),
+    Text {
+        keyword: String,
+        text: String,
+    },
+    Ztxt {
+        keyword: String,
+        text: String,
+    },
+    Itxt {
+        keyword: String,
+        compressed: bool,
+        language: String,
+        translated_keyword: String,
+        text: String,
+    },
+    Actl {
+        num_frames: u32,
+        num_plays: u32,
+    },
+    Fctl(FrameControl),
+    Fdat {
+        sequence_number: u32,
+        data: Vec<u8>,
+    },
+    Unknown(Chunk),
+}
+
+impl KnownChunk {
+    pub fn chunk_type(&self) -> ChunkType {
+        let name = match self {
+            KnownChunk::Ihdr(_) => "IHDR",
+            KnownChunk::Plte(_) => "PLTE",
+            KnownChunk::Trns(_) => "tRNS",
+            KnownChunk::Gama(_) => "gAMA",
+            KnownChunk::Chrm(_) => "cHRM",
+            KnownChunk::Srgb(_) => "sRGB",
+            KnownChunk::Iccp { .. } => "iCCP",
+            KnownChunk::Sbit(_) => "sBIT",
+            KnownChunk::Bkgd(_) => "bKGD",
+            KnownChunk::Hist(_) => "hIST",
+            KnownChunk::Phys { .. } => "pHYs",
+            KnownChunk::Splt { .. } => "sPLT",
+            KnownChunk::Time(_) => "tIME",
+            KnownChunk::Exif(_) => "eXIf",
+            KnownChunk::Text { .. } => "tEXt",
+            KnownChunk::Ztxt { .. } => "zTXt",
+            KnownChunk::Itxt { .. } => "iTXt",
+            KnownChunk::Actl { .. } => "acTL",
+            KnownChunk::Fctl(_) => "fcTL",
+            KnownChunk::Fdat { .. } => "fdAT",
+            KnownChunk::Unknown(chunk) => return chunk.chunk_type().clone(),
+        };
+        ChunkType::from_str(name).unwrap()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            KnownChunk::Ihdr(header) => {
+                let mut data = Vec::with_capacity(13);
+                data.extend(header.width.to_be_bytes());
+                data.extend(header.height.to_be_bytes());
+                data.extend([
+                    header.bit_depth,
+                    header.color_type.value(),
+                    header.compression_method,
+                    header.filter_method,
+                    header.interlace_method,
+                ]);
+                data
+            }
+            KnownChunk::Plte(entries) => entries
+                .iter()
+                .flat_map(|entry| [entry.red, entry.green, entry.blue])
+                .collect(),
+            KnownChunk::Trns(Transparency::Gray(gray)) => gray.to_be_bytes().to_vec(),
+            KnownChunk::Trns(Transparency::Rgb(red, green, blue)) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            KnownChunk::Trns(Transparency::Palette(alphas)) => alphas.clone(),
+            KnownChunk::Gama(gamma) => gamma.to_be_bytes().to_vec(),
+            KnownChunk::Chrm(chrm) => [
+                chrm.white_x,
+                chrm.white_y,
+                chrm.red_x,
+                chrm.red_y,
+                chrm.green_x,
+                chrm.green_y,
+                chrm.blue_x,
+                chrm.blue_y,
+            ]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect(),
+            KnownChunk::Srgb(intent) => vec![*intent],
+            KnownChunk::Iccp { name, profile } => {
+                let mut data = latin1_encode(name);
+                data.extend([0, 0]);
+                data.extend(deflate(profile));
+                data
+            }
+            KnownChunk::Sbit(bits) => bits.clone(),
+            KnownChunk::Bkgd(Background::PaletteIndex(index)) => vec![*index],
+            KnownChunk::Bkgd(Background::Gray(gray)) => gray.to_be_bytes().to_vec(),
+            KnownChunk::Bkgd(Background::Rgb(red, green, blue)) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            KnownChunk::Hist(frequencies) => frequencies
+                .iter()
+                .flat_map(|frequency| frequency.to_be_bytes())
+                .collect(),
+            KnownChunk::Phys { x, y, unit } => {
+                let mut data = Vec::with_capacity(9);
+                data.extend(x.to_be_bytes());
+                data.extend(y.to_be_bytes());
+                data.push(*unit);
+                data
+            }
+            KnownChunk::Splt {
+                name,
+                sample_depth,
+                entries,
+            } => {
+                let mut data = latin1_encode(name);
+                data.push(0);
+                data.push(*sample_depth);
+                for entry in entries {
+                    let samples = [entry.red, entry.green, entry.blue, entry.alpha];
+                    if *sample_depth == 8 {
+                        data.extend(samples.iter().map(|&sample| sample as u8));
+                    } else {
+                        data.extend(samples.iter().flat_map(|sample| sample.to_be_bytes()));
+                    }
+                    data.extend(entry.frequency.to_be_bytes());
+                }
+                data
+            }
+            KnownChunk::Time(time) => {
+                let mut data = time.year.to_be_bytes().to_vec();
+                data.extend([time.month, time.day, time.hour, time.minute, time.second]);
+                data
+            }
+            KnownChunk::Exif(exif) => exif.clone(),
+            KnownChunk::Text { keyword, text } => {
+                let mut data = latin1_encode(keyword);
+                data.push(0);
+                data.extend(latin1_encode(text));
+                data
+            }
+            KnownChunk::Ztxt { keyword, text } => {
+                let mut data = latin1_encode(keyword);
+                data.extend([0, 0]);
+                data.extend(deflate(&latin1_encode(text)));
+                data
+            }
+            KnownChunk::Itxt {
+                keyword,
+                compressed,
+                language,
+                translated_keyword,
+                text,
+            } => {
+                let mut data = latin1_encode(keyword);
+                data.extend([0, *compressed as u8, 0]);
+                data.extend(language.as_bytes());
+                data.push(0);
+                data.extend(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend(deflate(text.as_bytes()));
+                } else {
+                    data.extend(text.as_bytes());
+                }
+                data
+            }
+            KnownChunk::Actl {
+                num_frames,
+                num_plays,
+            } => {
+                let mut data = num_frames.to_be_bytes().to_vec();
+                data.extend(num_plays.to_be_bytes());
+                data
+            }
+            KnownChunk::Fctl(fctl) => {
+                let mut data = Vec::with_capacity(26);
+                data.extend(fctl.sequence_number.to_be_bytes());
+                data.extend(fctl.width.to_be_bytes());
+                data.extend(fctl.height.to_be_bytes());
+                data.extend(fctl.x_offset.to_be_bytes());
+                data.extend(fctl.y_offset.to_be_bytes());
+                data.extend(fctl.delay_num.to_be_bytes());
+                data.extend(fctl.delay_den.to_be_bytes());
+                data.extend([fctl.dispose_op, fctl.blend_op]);
+                data
+            }
+            KnownChunk::Fdat {
+                sequence_number,
+                data,
+            } => {
+                let mut bytes = sequence_number.to_be_bytes().to_vec();
+                bytes.extend(data);
+                bytes
+            }
+            KnownChunk::Unknown(chunk) => return chunk.clone(),
+        };
+
+        Chunk::new(self.chunk_type(), data)
+    }
+}
+
+impl Chunk {
+    pub fn parse_known(&self) -> Result<KnownChunk, Error> {
+        self.parse_known_with(None)
+    }
+
+    // `tRNS` cannot be told apart from its length alone, so callers that know
+    // the colour type from `IHDR` should pass it along.
+    pub fn parse_known_with(&self, color_type: Option<ColorType>) -> Result<KnownChunk, Error> {
+        let data = self.data();
+        let chunk_type = self.chunk_type().to_string();
+
+        let known = match chunk_type.as_str() {
+            "IHDR" => {
+                expect_length("IHDR", data, 13)?;
+                let color_type = ColorType::try_from(data[9])?;
+                if !color_type.bit_depths().contains(&data[8]) {
+                    return Err(malformed(
+                        "IHDR",
+                        "bit depth is not allowed for the colour type",
+                    ));
+                }
+                KnownChunk::Ihdr(ImageHeader {
+                    width: read_u32(data, 0),
+                    height: read_u32(data, 4),
+                    bit_depth: data[8],
+                    color_type,
+                    compression_method: data[10],
+                    filter_method: data[11],
+                    interlace_method: data[12],
+                })
+            }
+            "PLTE" => {
+                if !data.len().is_multiple_of(3) {
+                    return Err(malformed("PLTE", "length is not a multiple of 3"));
+                }
+                KnownChunk::Plte(
+                    data.chunks_exact(3)
+                        .map(|entry| PaletteEntry {
+                            red: entry[0],
+                            green: entry[1],
+                            blue: entry[2],
+                        })
+                        .collect(),
+                )
+            }
+            "tRNS" => {
+                let transparency = match (color_type, data.len()) {
+                    (Some(ColorType::Indexed), _) => Transparency::Palette(data.to_vec()),
+                    (Some(ColorType::Grayscale), 2) => Transparency::Gray(read_u16(data, 0)),
+                    (Some(ColorType::Rgb), 6) | (None, 6) => {
+                        Transparency::Rgb(read_u16(data, 0), read_u16(data, 2), read_u16(data, 4))
+                    }
+                    (None, _) => Transparency::Palette(data.to_vec()),
+                    _ => return Err(malformed("tRNS", "unexpected length for colour type")),
+                };
+                KnownChunk::Trns(transparency)
+            }
+            "gAMA" => {
+                expect_length("gAMA", data, 4)?;
+                KnownChunk::Gama(read_u32(data, 0))
+            }
+            "cHRM" => {
+                expect_length("cHRM", data, 32)?;
+                KnownChunk::Chrm(Chromaticities {
+                    white_x: read_u32(data, 0),
+                    white_y: read_u32(data, 4),
+                    red_x: read_u32(data, 8),
+                    red_y: read_u32(data, 12),
+                    green_x: read_u32(data, 16),
+                    green_y: read_u32(data, 20),
+                    blue_x: read_u32(data, 24),
+                    blue_y: read_u32(data, 28),
+                })
+            }
+            "sRGB" => {
+                expect_length("sRGB", data, 1)?;
+                KnownChunk::Srgb(data[0])
+            }
+            "iCCP" => {
+                let (name, rest) = split_keyword("iCCP", data)?;
+                let (_method, compressed) = rest
+                    .split_first()
+                    .ok_or(malformed("iCCP", "missing compression method"))?;
+                KnownChunk::Iccp {
+                    name,
+                    profile: inflate("iCCP", compressed)?,
+                }
+            }
+            "sBIT" => KnownChunk::Sbit(data.to_vec()),
+            "bKGD" => {
+                let background = match data.len() {
+                    1 => Background::PaletteIndex(data[0]),
+                    2 => Background::Gray(read_u16(data, 0)),
+                    6 => Background::Rgb(read_u16(data, 0), read_u16(data, 2), read_u16(data, 4)),
+                    _ => return Err(malformed("bKGD", "unexpected length")),
+                };
+                KnownChunk::Bkgd(background)
+            }
+            "hIST" => {
+                if !data.len().is_multiple_of(2) {
+                    return Err(malformed("hIST", "length is not a multiple of 2"));
+                }
+                KnownChunk::Hist(data.chunks_exact(2).map(|pair| read_u16(pair, 0)).collect())
+            }
+            "pHYs" => {
+                expect_length("pHYs", data, 9)?;
+                KnownChunk::Phys {
+                    x: read_u32(data, 0),
+                    y: read_u32(data, 4),
+                    unit: data[8],
+                }
+            }
+            "sPLT" => {
+                let (name, rest) = split_keyword("sPLT", data)?;
+                let (&sample_depth, entries) = rest
+                    .split_first()
+                    .ok_or(malformed("sPLT", "missing sample depth"))?;
+                let entry_size = match sample_depth {
+                    8 => 6,
+                    16 => 10,
+                    _ => return Err(malformed("sPLT", "sample depth must be 8 or 16")),
+                };
+                if entries.len() % entry_size != 0 {
+                    return Err(malformed("sPLT", "truncated palette entry"));
+                }
+                let entries = entries
+                    .chunks_exact(entry_size)
+                    .map(|entry| {
+                        if sample_depth == 8 {
+                            SuggestedPaletteEntry {
+                                red: entry[0] as u16,
+                                green: entry[1] as u16,
+                                blue: entry[2] as u16,
+                                alpha: entry[3] as u16,
+                                frequency: read_u16(entry, 4),
+                            }
+                        } else {
+                            SuggestedPaletteEntry {
+                                red: read_u16(entry, 0),
+                                green: read_u16(entry, 2),
+                                blue: read_u16(entry, 4),
+                                alpha: read_u16(entry, 6),
+                                frequency: read_u16(entry, 8),
+                            }
+                        }
+                    })
+                    .collect();
+                KnownChunk::Splt {
+                    name,
+                    sample_depth,
+                    entries,
+                }
+            }
+            "tIME" => {
+                expect_length("tIME", data, 7)?;
+                KnownChunk::Time(Timestamp {
+                    year: read_u16(data, 0),
+                    month: data[2],
+                    day: data[3],
+                    hour: data[4],
+                    minute: data[5],
+                    second: data[6],
+                })
+            }
+            "eXIf" => KnownChunk::Exif(data.to_vec()),
+            "tEXt" => {
+                let (keyword, text) = split_keyword("tEXt", data)?;
+                KnownChunk::Text {
+                    keyword,
+                    text: latin1_decode(text),
+                }
+            }
+            "zTXt" => {
+                let (keyword, rest) = split_keyword("zTXt", data)?;
+                let (_method, compressed) = rest
+                    .split_first()
+                    .ok_or(malformed("zTXt", "missing compression method"))?;
+                KnownChunk::Ztxt {
+                    keyword,
+                    text: latin1_decode(&inflate("zTXt", compressed)?),
+                }
+            }
+            "iTXt" => {
+                let (keyword, rest) = split_keyword("iTXt", data)?;
+                if rest.len() < 2 {
+                    return Err(malformed("iTXt", "missing compression fields"));
+                }
+                let compressed = rest[0] == 1;
+                let (language, rest) = split_keyword("iTXt", &rest[2..])?;
+                let (translated_keyword, rest) = split_utf8("iTXt", rest)?;
+                let text = if compressed {
+                    inflate("iTXt", rest)?
+                } else {
+                    rest.to_vec()
+                };
+                KnownChunk::Itxt {
+                    keyword,
+                    compressed,
+                    language,
+                    translated_keyword,
+                    text: String::from_utf8(text)
+                        .map_err(|_| malformed("iTXt", "text is not valid UTF-8"))?,
+                }
+            }
+            "acTL" => {
+                expect_length("acTL", data, 8)?;
+                KnownChunk::Actl {
+                    num_frames: read_u32(data, 0),
+                    num_plays: read_u32(data, 4),
+                }
+            }
+            "fcTL" => {
+                expect_length("fcTL", data, 26)?;
+                KnownChunk::Fctl(FrameControl {
+                    sequence_number: read_u32(data, 0),
+                    width: read_u32(data, 4),
+                    height: read_u32(data, 8),
+                    x_offset: read_u32(data, 12),
+                    y_offset: read_u32(data, 16),
+                    delay_num: read_u16(data, 20),
+                    delay_den: read_u16(data, 22),
+                    dispose_op: data[24],
+                    blend_op: data[25],
+                })
+            }
+            "fdAT" => {
+                if data.len() < 4 {
+                    return Err(malformed("fdAT", "missing sequence number"));
+                }
+                KnownChunk::Fdat {
+                    sequence_number: read_u32(data, 0),
+                    data: data[4..].to_vec(),
+                }
+            }
+            _ => KnownChunk::Unknown(self.clone()),
+        };
+
+        Ok(known)
+    }
+}
+
+impl Display for KnownChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KnownChunk::Ihdr(header) => write!(
+                f,
+                "{}x{}, {}-bit {}, {}",
+                header.width,
+                header.height,
+                header.bit_depth,
+                header.color_type,
+                if header.interlace_method == 1 {
+                    "Adam7 interlaced"
+                } else {
+                    "non-interlaced"
+                }
+            ),
+            KnownChunk::Plte(entries) => write!(f, "{} palette entries", entries.len()),
+            KnownChunk::Trns(Transparency::Gray(gray)) => write!(f, "transparent gray {}", gray),
+            KnownChunk::Trns(Transparency::Rgb(red, green, blue)) => {
+                write!(f, "transparent rgb({}, {}, {})", red, green, blue)
+            }
+            KnownChunk::Trns(Transparency::Palette(alphas)) => {
+                write!(f, "alpha for {} palette entries", alphas.len())
+            }
+            KnownChunk::Gama(gamma) => write!(f, "gamma {:.5}", *gamma as f64 / 100000.0),
+            KnownChunk::Chrm(chrm) => {
+                let point = |x: u32, y: u32| {
+                    format!("({:.4}, {:.4})", x as f64 / 100000.0, y as f64 / 100000.0)
+                };
+                write!(
+                    f,
+                    "white {} red {} green {} blue {}",
+                    point(chrm.white_x, chrm.white_y),
+                    point(chrm.red_x, chrm.red_y),
+                    point(chrm.green_x, chrm.green_y),
+                    point(chrm.blue_x, chrm.blue_y)
+                )
+            }
+            KnownChunk::Srgb(intent) => {
+                let intent = match intent {
+                    0 => "perceptual",
+                    1 => "relative colorimetric",
+                    2 => "saturation",
+                    3 => "absolute colorimetric",
+                    _ => "unknown",
+                };
+                write!(f, "rendering intent: {}", intent)
+            }
+            KnownChunk::Iccp { name, profile } => {
+                write!(f, "profile \"{}\" ({} bytes)", name, profile.len())
+            }
+            KnownChunk::Sbit(bits) => write!(f, "significant bits {:?}", bits),
+            KnownChunk::Bkgd(Background::PaletteIndex(index)) => {
+                write!(f, "background palette index {}", index)
+            }
+            KnownChunk::Bkgd(Background::Gray(gray)) => write!(f, "background gray {}", gray),
+            KnownChunk::Bkgd(Background::Rgb(red, green, blue)) => {
+                write!(f, "background rgb({}, {}, {})", red, green, blue)
+            }
+            KnownChunk::Hist(frequencies) => {
+                write!(f, "histogram of {} palette entries", frequencies.len())
+            }
+            KnownChunk::Phys { x, y, unit } => {
+                if *unit == 1 {
+                    write!(
+                        f,
+                        "{}x{} pixels per metre ({:.0}x{:.0} DPI)",
+                        x,
+                        y,
+                        *x as f64 * 0.0254,
+                        *y as f64 * 0.0254
+                    )
+                } else {
+                    write!(f, "aspect ratio {}:{}", x, y)
+                }
+            }
+            KnownChunk::Splt {
+                name,
+                sample_depth,
+                entries,
+            } => write!(
+                f,
+                "suggested palette \"{}\", {} entries at {}-bit",
+                name,
+                entries.len(),
+                sample_depth
+            ),
+            KnownChunk::Time(time) => write!(
+                f,
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                time.year, time.month, time.day, time.hour, time.minute, time.second
+            ),
+            KnownChunk::Exif(exif) => write!(f, "{} bytes of Exif data", exif.len()),
+            KnownChunk::Text { keyword, text } | KnownChunk::Ztxt { keyword, text } => {
+                write!(f, "{}: {}", keyword, text)
+            }
+            KnownChunk::Itxt {
+                keyword,
+                language,
+                text,
+                ..
+            } => {
+                if language.is_empty() {
+                    write!(f, "{}: {}", keyword, text)
+                } else {
+                    write!(f, "{} [{}]: {}", keyword, language, text)
+                }
+            }
+            KnownChunk::Actl {
+                num_frames,
+                num_plays,
+            } => {
+                if *num_plays == 0 {
+                    write!(f, "{} frames, loops forever", num_frames)
+                } else {
+                    write!(f, "{} frames, plays {} times", num_frames, num_plays)
+                }
+            }
+            KnownChunk::Fctl(fctl) => write!(
+                f,
+                "seq {}, {}x{} at ({}, {}), delay {}/{} ({:.3}s)",
+                fctl.sequence_number,
+                fctl.width,
+                fctl.height,
+                fctl.x_offset,
+                fctl.y_offset,
+                fctl.delay_num,
+                fctl.delay_den,
+                fctl.delay_seconds()
+            ),
+            KnownChunk::Fdat {
+                sequence_number, ..
+            } => write!(f, "seq {}", sequence_number),
+            KnownChunk::Unknown(chunk) => write!(f, "{} bytes", chunk.length()),
+        }
+    }
+}
+
+fn malformed(chunk_type: &'static str, reason: &'static str) -> Error {
+    Error::MalformedChunk { chunk_type, reason }
+}
+
+fn expect_length(chunk_type: &'static str, data: &[u8], length: usize) -> Result<(), Error> {
+    if data.len() != length {
+        return Err(malformed(chunk_type, "unexpected length"));
+    }
+    Ok(())
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn split_keyword<'a>(
+    chunk_type: &'static str,
+    data: &'a [u8],
+) -> Result<(String, &'a [u8]), Error> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(malformed(chunk_type, "missing null separator"))?;
+    Ok((latin1_decode(&data[..end]), &data[end + 1..]))
+}
+
+fn split_utf8<'a>(chunk_type: &'static str, data: &'a [u8]) -> Result<(String, &'a [u8]), Error> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or(malformed(chunk_type, "missing null separator"))?;
+    let value = String::from_utf8(data[..end].to_vec())
+        .map_err(|_| malformed(chunk_type, "field is not valid UTF-8"))?;
+    Ok((value, &data[end + 1..]))
+}
+
+fn latin1_decode(data: &[u8]) -> String {
+    data.iter().map(|&byte| byte as char).collect()
+}
+
+fn latin1_encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(c as u32).unwrap_or(b'?'))
+        .collect()
+}
+
+// Text and ICC profiles are small, so a chunk that inflates past this is
+// taken for a zlib bomb.
+const MAX_INFLATED_SIZE: u64 = 16 * 1024 * 1024;
+
+fn inflate(chunk_type: &'static str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    ZlibDecoder::new(data)
+        .take(MAX_INFLATED_SIZE + 1)
+        .read_to_end(&mut output)
+        .map_err(|_| malformed(chunk_type, "invalid zlib stream"))?;
+    if output.len() as u64 > MAX_INFLATED_SIZE {
+        return Err(malformed(chunk_type, "inflates past the 16 MiB limit"));
+    }
+    Ok(output)
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_parse_phys() {
+        let known = chunk("pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1])
+            .parse_known()
+            .unwrap();
+        assert!(matches!(
+            known,
+            KnownChunk::Phys {
+                x: 2835,
+                y: 2835,
+                unit: 1
+            }
+        ));
+        assert_eq!(known.to_string(), "2835x2835 pixels per metre (72x72 DPI)");
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let known = chunk("tIME", &[7, 231, 7, 1, 12, 30, 5])
+            .parse_known()
+            .unwrap();
+        assert_eq!(known.to_string(), "2023-07-01 12:30:05 UTC");
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let known = chunk("tEXt", b"Author\0Ryan").parse_known().unwrap();
+        assert_eq!(known.to_string(), "Author: Ryan");
+    }
+
+    #[test]
+    fn test_ztxt_round_trip() {
+        let original = KnownChunk::Ztxt {
+            keyword: String::from("Comment"),
+            text: String::from("compressed comment"),
+        };
+        let reparsed = original.to_chunk().parse_known().unwrap();
+        assert_eq!(reparsed.to_string(), "Comment: compressed comment");
+    }
+
+    #[test]
+    fn test_itxt_round_trip() {
+        let original = KnownChunk::Itxt {
+            keyword: String::from("Title"),
+            compressed: true,
+            language: String::from("ja"),
+            translated_keyword: String::from("タイトル"),
+            text: String::from("猫"),
+        };
+        let chunk = original.to_chunk();
+        match chunk.parse_known().unwrap() {
+            KnownChunk::Itxt {
+                translated_keyword,
+                text,
+                ..
+            } => {
+                assert_eq!(translated_keyword, "タイトル");
+                assert_eq!(text, "猫");
+            }
+            other => panic!("unexpected chunk {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_back_is_byte_identical() {
+        let original = chunk("cHRM", &(0..32).collect::<Vec<u8>>());
+        let encoded = original.parse_known().unwrap().to_chunk();
+        assert_eq!(encoded.as_bytes(), original.as_bytes());
+    }
+
+    #[test]
+    fn test_trns_uses_color_type() {
+        let trns = chunk("tRNS", &[0, 7]);
+        assert!(matches!(
+            trns.parse_known_with(Some(ColorType::Grayscale)).unwrap(),
+            KnownChunk::Trns(Transparency::Gray(7))
+        ));
+        assert!(matches!(
+            trns.parse_known_with(Some(ColorType::Indexed)).unwrap(),
+            KnownChunk::Trns(Transparency::Palette(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_falls_back_to_raw_chunk() {
+        let known = chunk("RuSt", b"secret").parse_known().unwrap();
+        assert!(matches!(known, KnownChunk::Unknown(_)));
+        assert_eq!(known.to_chunk().data(), b"secret");
+    }
+
+    #[test]
+    fn test_malformed_chunk() {
+        assert!(chunk("gAMA", &[1, 2]).parse_known().is_err());
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        let bomb = deflate(&vec![0; MAX_INFLATED_SIZE as usize + 1]);
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(bomb);
+        assert!(matches!(
+            chunk("zTXt", &data).parse_known(),
+            Err(Error::MalformedChunk {
+                chunk_type: "zTXt",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,936 @@
+use std::{cmp::Reverse, fmt::Display, str::FromStr};
+
+use clap::{Args, ValueEnum};
+use rand::{rngs::OsRng, seq::SliceRandom};
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bits,
+    errors::Error,
+    image::{self, RawImage},
+    known_chunk::ColorType,
+};
+
+const HEADER_BITS: usize = 16;
+const HEADER_MAGIC: u32 = 0b1011;
+const LENGTH_PREFIX_BITS: usize = 32;
+const TRAVERSAL_CONTEXT: &[u8] = b"hidepng lsb traversal v1";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+    // overwrite the low bits
+    #[default]
+    Replace,
+    // move to the nearest value with the right low bits, up or down
+    Matching,
+    // Hamming-code blocks, at most one change per block of 2^k - 1 samples
+    Matrix,
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Replace => write!(f, "replace"),
+            Strategy::Matching => write!(f, "matching"),
+            Strategy::Matrix => write!(f, "matrix"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TransparentPixels {
+    #[default]
+    Use,
+    Skip,
+    Only,
+}
+
+// Set of channels to write to. Gray and palette samples count as colour and
+// are picked by any of r, g and b.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Channels(u8);
+
+impl Channels {
+    const RED: u8 = 0b0001;
+    const GREEN: u8 = 0b0010;
+    const BLUE: u8 = 0b0100;
+    const ALPHA: u8 = 0b1000;
+    const LETTERS: [(char, u8); 4] = [
+        ('r', Self::RED),
+        ('g', Self::GREEN),
+        ('b', Self::BLUE),
+        ('a', Self::ALPHA),
+    ];
+
+    fn has_alpha(&self) -> bool {
+        self.0 & Self::ALPHA != 0
+    }
+
+    fn selects(&self, color_type: ColorType, channel: usize) -> bool {
+        let colour = Self::RED | Self::GREEN | Self::BLUE;
+        let bit = match (color_type, channel) {
+            (ColorType::Rgb | ColorType::Rgba, 0) => Self::RED,
+            (ColorType::Rgb | ColorType::Rgba, 1) => Self::GREEN,
+            (ColorType::Rgb | ColorType::Rgba, 2) => Self::BLUE,
+            (ColorType::Rgba, 3) | (ColorType::GrayscaleAlpha, 1) => Self::ALPHA,
+            _ => colour,
+        };
+        self.0 & bit != 0
+    }
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels(Self::RED | Self::GREEN | Self::BLUE)
+    }
+}
+
+impl FromStr for Channels {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mask = 0;
+        for letter in s.to_ascii_lowercase().chars() {
+            let (_, bit) = Self::LETTERS
+                .iter()
+                .find(|(name, _)| *name == letter)
+                .ok_or(Error::IncompatibleOptions(
+                    "--channels takes the letters r, g, b and a",
+                ))?;
+            mask |= bit;
+        }
+        if mask == 0 {
+            return Err(Error::IncompatibleOptions("--channels can't be empty"));
+        }
+        Ok(Channels(mask))
+    }
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (letter, bit) in Self::LETTERS {
+            if self.0 & bit != 0 {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LsbOptions {
+    #[arg(
+        long,
+        help = "Key that scatters the message over the pixels with lsb or palette methods."
+    )]
+    pub key: Option<String>,
+    #[arg(long, value_enum, default_value_t = Strategy::Replace, help = "How message bits are written by lsb or palette methods.")]
+    pub strategy: Strategy,
+    #[arg(
+        long,
+        help = "Only use textured parts of the image, where changes are hardest to see."
+    )]
+    pub adaptive: bool,
+    #[arg(long, default_value_t = Channels::default(), help = "Channels to hide bits in, any of r, g, b and a.")]
+    pub channels: Channels,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=8), help = "Low bits of each sample to use.")]
+    pub bits_per_sample: u8,
+    #[arg(long, value_enum, default_value_t = TransparentPixels::Use, help = "Whether fully transparent pixels are used, skipped or the only ones used.")]
+    pub transparent_pixels: TransparentPixels,
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions {
+            key: None,
+            strategy: Strategy::default(),
+            adaptive: false,
+            channels: Channels::default(),
+            bits_per_sample: 1,
+            transparent_pixels: TransparentPixels::default(),
+        }
+    }
+}
+
+impl LsbOptions {
+    // Everything except the key is stored in a 16 bit header: a 4 bit magic
+    // number, the channel mask, bits per sample - 1, the transparency mode,
+    // the strategy and the adaptive flag.
+    fn header(&self) -> u32 {
+        let transparent_pixels = match self.transparent_pixels {
+            TransparentPixels::Use => 0,
+            TransparentPixels::Skip => 1,
+            TransparentPixels::Only => 2,
+        };
+        let strategy = match self.strategy {
+            Strategy::Replace => 0,
+            Strategy::Matching => 1,
+            Strategy::Matrix => 2,
+        };
+        HEADER_MAGIC << 12
+            | (self.channels.0 as u32) << 8
+            | (self.bits_per_sample as u32 - 1) << 5
+            | transparent_pixels << 3
+            | strategy << 1
+            | self.adaptive as u32
+    }
+
+    fn from_header(header: u32, key: Option<&str>) -> Result<Self, Error> {
+        if header >> 12 != HEADER_MAGIC || (header >> 8) & 0b1111 == 0 {
+            return Err(Error::NoHiddenData);
+        }
+        Ok(LsbOptions {
+            key: key.map(str::to_string),
+            strategy: match (header >> 1) & 0b11 {
+                0 => Strategy::Replace,
+                1 => Strategy::Matching,
+                2 => Strategy::Matrix,
+                _ => return Err(Error::NoHiddenData),
+            },
+            adaptive: header & 1 == 1,
+            channels: Channels((header >> 8) as u8 & 0b1111),
+            bits_per_sample: ((header >> 5) & 0b111) as u8 + 1,
+            transparent_pixels: match (header >> 3) & 0b11 {
+                0 => TransparentPixels::Use,
+                1 => TransparentPixels::Skip,
+                2 => TransparentPixels::Only,
+                _ => return Err(Error::NoHiddenData),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub strategy: Strategy,
+    pub adaptive: bool,
+    pub bits_per_sample: u8,
+    pub payload_bits: usize,
+    pub samples_available: usize,
+    pub samples_used: usize,
+    // message bits per block of samples: k bits in 2^k - 1 samples with
+    // matrix coding, otherwise the bits per sample in a single sample
+    pub bits_per_block: u32,
+    pub block_size: usize,
+}
+
+impl Plan {
+    pub fn capacity(&self) -> usize {
+        (self.samples_available - HEADER_BITS - LENGTH_PREFIX_BITS) * self.bits_per_sample as usize
+            / 8
+    }
+
+    // A changed sample is needed whenever a block's bits don't already match,
+    // which for random data happens with probability 1 - 2^-k.
+    pub fn expected_changes(&self) -> f64 {
+        let blocks = self.payload_bits.div_ceil(self.bits_per_block as usize);
+        (HEADER_BITS + LENGTH_PREFIX_BITS) as f64 / 2.0
+            + blocks as f64 * (1.0 - 0.5f64.powi(self.bits_per_block as i32))
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes = self.expected_changes();
+        write!(
+            f,
+            "strategy {}{}: {} bits per {} samples, {} of {} {}samples used, about {:.0} changed ({:.2}% of used samples, {:.3} per message bit), capacity {} bytes",
+            self.strategy,
+            if self.adaptive { " (adaptive)" } else { "" },
+            self.bits_per_block,
+            self.block_size,
+            self.samples_used,
+            self.samples_available,
+            if self.adaptive { "textured " } else { "" },
+            changes,
+            changes * 100.0 / self.samples_used.max(1) as f64,
+            changes / (LENGTH_PREFIX_BITS + self.payload_bits) as f64,
+            self.capacity(),
+        )
+    }
+}
+
+// Positions of the header, the length prefix and the message body.
+struct Layout {
+    header: Vec<usize>,
+    prefix: Vec<usize>,
+    body: Vec<usize>,
+}
+
+pub fn plan(image: &RawImage, length: usize, options: &LsbOptions) -> Result<Plan, Error> {
+    check_color_type(image)?;
+    plan_samples(image, length, options)
+}
+
+pub fn plan_samples(image: &RawImage, length: usize, options: &LsbOptions) -> Result<Plan, Error> {
+    let layout = layout(image, options)?;
+    plan_for(&layout, length, options)
+}
+
+fn plan_for(layout: &Layout, length: usize, options: &LsbOptions) -> Result<Plan, Error> {
+    let strategy = options.strategy;
+    let bits_per_sample = options.bits_per_sample as usize;
+    let body_samples = layout.body.len();
+
+    let available = body_samples * bits_per_sample / 8;
+    if length > available {
+        return Err(Error::CapacityExceeded {
+            needed: length,
+            available,
+        });
+    }
+
+    let payload_bits = length * 8;
+    let (bits_per_block, block_size) = match strategy {
+        Strategy::Matrix => {
+            let k = matrix_bits_per_block(payload_bits, body_samples);
+            (k, (1usize << k) - 1)
+        }
+        _ => (bits_per_sample as u32, 1),
+    };
+
+    Ok(Plan {
+        strategy,
+        adaptive: options.adaptive,
+        bits_per_sample: options.bits_per_sample,
+        payload_bits,
+        samples_available: HEADER_BITS + LENGTH_PREFIX_BITS + body_samples,
+        samples_used: HEADER_BITS
+            + LENGTH_PREFIX_BITS
+            + payload_bits.div_ceil(bits_per_block as usize) * block_size,
+        bits_per_block,
+        block_size,
+    })
+}
+
+pub fn embed(image: &mut RawImage, data: &[u8], options: &LsbOptions) -> Result<Plan, Error> {
+    check_color_type(image)?;
+    embed_samples(image, data, options)
+}
+
+pub fn extract(image: &RawImage, key: Option<&str>) -> Result<Vec<u8>, Error> {
+    check_color_type(image)?;
+    extract_samples(image, key)
+}
+
+// Writes into whatever the samples are, palette indices included. Callers are
+// responsible for making a low bit change harmless.
+pub fn embed_samples(
+    image: &mut RawImage,
+    data: &[u8],
+    options: &LsbOptions,
+) -> Result<Plan, Error> {
+    let layout = layout(image, options)?;
+    let plan = plan_for(&layout, data.len(), options)?;
+    // the header and length are always one bit per sample
+    let single_bit = match options.strategy {
+        Strategy::Matching => Strategy::Matching,
+        _ => Strategy::Replace,
+    };
+
+    let header = bits::bits_of_value(options.header(), HEADER_BITS as u32);
+    let length = bits::bits_of_value(data.len() as u32, LENGTH_PREFIX_BITS as u32);
+    for (&index, bit) in layout
+        .header
+        .iter()
+        .chain(&layout.prefix)
+        .zip(header.chain(length))
+    {
+        set_low_bits(image, index, bit as u16, 1, single_bit);
+    }
+
+    let bits = bits::bits_of(data);
+    let k = plan.bits_per_block as usize;
+    if options.strategy != Strategy::Matrix {
+        for (&index, value) in layout.body.iter().zip(bits.chunks(k)) {
+            let value = bits::value_from_bits(value) << (k - value.len());
+            set_low_bits(image, index, value as u16, k as u32, options.strategy);
+        }
+        return Ok(plan);
+    }
+
+    for (block, message) in layout
+        .body
+        .chunks_exact(plan.block_size)
+        .zip(bits.chunks(k))
+    {
+        let wanted = bits::value_from_bits(message) << (k - message.len());
+        let flip = syndrome(image, block) ^ wanted as usize;
+        if flip != 0 {
+            let index = block[flip - 1];
+            image.set_sample(index, image.sample(index) ^ 1);
+        }
+    }
+    Ok(plan)
+}
+
+pub fn extract_samples(image: &RawImage, key: Option<&str>) -> Result<Vec<u8>, Error> {
+    let header_positions = header_positions(image, key)?;
+    let low_bit = |index: &usize| image.sample(*index) & 1 == 1;
+    let header: Vec<bool> = header_positions.iter().map(low_bit).collect();
+    let options = LsbOptions::from_header(bits::value_from_bits(&header), key)?;
+
+    let layout = layout(image, &options).map_err(|_| Error::NoHiddenData)?;
+    let length = bits::value_from_bits(&layout.prefix.iter().map(low_bit).collect::<Vec<_>>());
+    let plan = plan_for(&layout, length as usize, &options).map_err(|err| match err {
+        Error::CapacityExceeded { .. } => Error::NoHiddenData,
+        err => err,
+    })?;
+
+    let k = plan.bits_per_block;
+    let blocks = plan.payload_bits.div_ceil(k as usize);
+    let bits: Vec<bool> = if options.strategy != Strategy::Matrix {
+        layout.body[..blocks]
+            .iter()
+            .flat_map(|&index| bits::bits_of_value(image.sample(index) as u32 & ((1 << k) - 1), k))
+            .take(plan.payload_bits)
+            .collect()
+    } else {
+        layout
+            .body
+            .chunks_exact(plan.block_size)
+            .take(blocks)
+            .flat_map(|block| bits::bits_of_value(syndrome(image, block) as u32, k))
+            .take(plan.payload_bits)
+            .collect()
+    };
+    Ok(bits::bytes_from_bits(&bits))
+}
+
+// Largest k whose blocks still fit, k = 1 being plain one bit per sample.
+fn matrix_bits_per_block(payload_bits: usize, samples: usize) -> u32 {
+    (1..=16)
+        .rev()
+        .find(|&k| payload_bits.div_ceil(k as usize) * ((1 << k) - 1) <= samples)
+        .unwrap_or(1)
+}
+
+// XOR of the (1-based) positions in the block whose low bit is set.
+fn syndrome(image: &RawImage, block: &[usize]) -> usize {
+    block
+        .iter()
+        .enumerate()
+        .filter(|(_, &index)| image.sample(index) & 1 == 1)
+        .fold(0, |acc, (position, _)| acc ^ (position + 1))
+}
+
+fn set_low_bits(image: &mut RawImage, index: usize, value: u16, bits: u32, strategy: Strategy) {
+    let sample = image.sample(index) as i32;
+    let modulus = 1i32 << bits;
+    if sample % modulus == value as i32 {
+        return;
+    }
+
+    let replaced = (sample & !(modulus - 1)) | value as i32;
+    let new = match strategy {
+        Strategy::Matching => {
+            let max = (1i32 << image.header.bit_depth) - 1;
+            let candidates: Vec<i32> = [replaced - modulus, replaced, replaced + modulus]
+                .into_iter()
+                .filter(|candidate| (0..=max).contains(candidate))
+                .collect();
+            let nearest = candidates
+                .iter()
+                .map(|candidate| candidate.abs_diff(sample))
+                .min()
+                .unwrap();
+            // ties, as with a single bit, go up or down at random
+            **candidates
+                .iter()
+                .filter(|candidate| candidate.abs_diff(sample) == nearest)
+                .collect::<Vec<_>>()
+                .choose(&mut OsRng)
+                .unwrap()
+        }
+        _ => replaced,
+    };
+    image.set_sample(index, new as u16);
+}
+
+fn check_color_type(image: &RawImage) -> Result<(), Error> {
+    if image.header.color_type == ColorType::Indexed {
+        return Err(Error::UnsupportedImage(
+            "LSB embedding does not support palette images, use --method palette",
+        ));
+    }
+    Ok(())
+}
+
+fn check_options(image: &RawImage, options: &LsbOptions) -> Result<(), Error> {
+    let header = &image.header;
+
+    if options.adaptive && options.strategy == Strategy::Matching {
+        return Err(Error::IncompatibleOptions(
+            "--adaptive can't be used with --strategy matching",
+        ));
+    }
+    if options.strategy == Strategy::Matrix && options.bits_per_sample > 1 {
+        return Err(Error::IncompatibleOptions(
+            "--strategy matrix only works with one bit per sample",
+        ));
+    }
+    if options.transparent_pixels != TransparentPixels::Use {
+        if !has_alpha(image) {
+            return Err(Error::IncompatibleOptions(
+                "--transparent-pixels needs an image with an alpha channel",
+            ));
+        }
+        if options.channels.has_alpha() {
+            return Err(Error::IncompatibleOptions(
+                "--transparent-pixels needs the alpha channel left out of --channels",
+            ));
+        }
+    }
+    if !(0..image::channels(header.color_type))
+        .any(|channel| options.channels.selects(header.color_type, channel))
+    {
+        return Err(Error::IncompatibleOptions(
+            "--channels selects no channel of this image",
+        ));
+    }
+
+    // Half the sample is the most that stays inconspicuous, which for 16-bit
+    // images is the whole low byte. Nothing shows through a transparent pixel,
+    // so those can be used entirely. Palette indices only come in pairs.
+    let max_bits = match (header.color_type, options.transparent_pixels) {
+        (ColorType::Indexed, _) => 1,
+        (_, TransparentPixels::Only) => header.bit_depth.min(8),
+        _ => (header.bit_depth / 2).clamp(1, 8),
+    };
+    if options.bits_per_sample > max_bits {
+        return Err(Error::IncompatibleOptions(
+            "--bits-per-sample is too large for this image's bit depth",
+        ));
+    }
+    Ok(())
+}
+
+fn has_alpha(image: &RawImage) -> bool {
+    matches!(
+        image.header.color_type,
+        ColorType::Rgba | ColorType::GrayscaleAlpha
+    )
+}
+
+fn is_alpha(image: &RawImage, index: usize) -> bool {
+    let channels = image::channels(image.header.color_type);
+    has_alpha(image) && index % channels == channels - 1
+}
+
+fn is_transparent(image: &RawImage, index: usize) -> bool {
+    let channels = image::channels(image.header.color_type);
+    let alpha = index - index % channels + channels - 1;
+    image.sample(alpha) == 0
+}
+
+// The header takes the first colour samples of the traversal, so it can be
+// found before anything else is known.
+fn header_positions(image: &RawImage, key: Option<&str>) -> Result<Vec<usize>, Error> {
+    let header: Vec<usize> = traversal(image.sample_count(), key)
+        .into_iter()
+        .filter(|&index| !is_alpha(image, index))
+        .take(HEADER_BITS)
+        .collect();
+    if header.len() < HEADER_BITS {
+        return Err(Error::CapacityExceeded {
+            needed: 0,
+            available: 0,
+        });
+    }
+    Ok(header)
+}
+
+// After the header, the length prefix takes the next samples the options
+// allow. With `adaptive` the message body then only visits samples that differ
+// from their neighbours, busiest first; ties keep the traversal order.
+fn layout(image: &RawImage, options: &LsbOptions) -> Result<Layout, Error> {
+    check_options(image, options)?;
+    let header = header_positions(image, options.key.as_deref())?;
+
+    let color_type = image.header.color_type;
+    let channels = image::channels(color_type);
+    let mut body: Vec<usize> = traversal(image.sample_count(), options.key.as_deref())
+        .into_iter()
+        .filter(|index| !header.contains(index))
+        .filter(|&index| options.channels.selects(color_type, index % channels))
+        .filter(|&index| match options.transparent_pixels {
+            TransparentPixels::Use => true,
+            TransparentPixels::Skip => !is_transparent(image, index),
+            TransparentPixels::Only => is_transparent(image, index),
+        })
+        .collect();
+    if body.len() < LENGTH_PREFIX_BITS {
+        return Err(Error::CapacityExceeded {
+            needed: 0,
+            available: 0,
+        });
+    }
+    let prefix = body.drain(..LENGTH_PREFIX_BITS).collect();
+
+    if options.adaptive {
+        let texture = texture(image, options.bits_per_sample);
+        body.retain(|&index| texture[index] > 0);
+        body.sort_by_key(|&index| Reverse(texture[index]));
+    }
+    Ok(Layout {
+        header,
+        prefix,
+        body,
+    })
+}
+
+// Sum of absolute differences to the four neighbouring samples of the same
+// channel. The bits embedding may touch are dropped first so embedding leaves
+// the map unchanged and extraction sees the same ordering.
+fn texture(image: &RawImage, bits_per_sample: u8) -> Vec<u32> {
+    let channels = image::channels(image.header.color_type);
+    let row = image.header.width as usize * channels;
+    let count = image.sample_count();
+    let values: Vec<i32> = (0..count)
+        .map(|index| (image.sample(index) >> bits_per_sample) as i32)
+        .collect();
+
+    (0..count)
+        .map(|index| {
+            let column = index % row;
+            [
+                (column >= channels).then(|| index - channels),
+                (column + channels < row).then(|| index + channels),
+                index.checked_sub(row),
+                Some(index + row).filter(|&below| below < count),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|neighbour| values[index].abs_diff(values[neighbour]))
+            .sum()
+        })
+        .collect()
+}
+
+// Without a key samples are visited in order. With one, the order is a
+// permutation drawn from a ChaCha20 stream seeded by the key, which scatters
+// the message over the whole image.
+fn traversal(samples: usize, key: Option<&str>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..samples).collect();
+    if let Some(key) = key {
+        let seed = Sha256::new()
+            .chain_update(TRAVERSAL_CONTEXT)
+            .chain_update(key.as_bytes())
+            .finalize();
+        order.shuffle(&mut ChaCha20Rng::from_seed(seed.into()));
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    fn options(key: Option<&str>, strategy: Strategy) -> LsbOptions {
+        LsbOptions {
+            key: key.map(str::to_string),
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    // The top of a real photo, cropped to keep the tests quick.
+    fn testing_image() -> RawImage {
+        let contents = std::fs::read("assets/ryanGosling.png").unwrap();
+        let mut image = RawImage::from_png(&Png::try_from(contents.as_slice()).unwrap()).unwrap();
+        let stride = image.data.len() / image.header.height as usize;
+        image.header.height = 160;
+        image.data.truncate(stride * 160);
+        image
+    }
+
+    // The testing image with an alpha channel, transparent on the left half.
+    fn testing_rgba_image() -> RawImage {
+        let mut image = testing_image();
+        let width = image.header.width as usize;
+        image.data = image
+            .data
+            .chunks(3)
+            .enumerate()
+            .flat_map(|(pixel, rgb)| {
+                let alpha = if pixel % width < width / 2 { 0 } else { 255 };
+                [rgb[0], rgb[1], rgb[2], alpha]
+            })
+            .collect();
+        image.header.color_type = ColorType::Rgba;
+        image
+    }
+
+    fn testing_16_bit_image() -> RawImage {
+        let mut image = testing_image();
+        image.data = image.data.iter().flat_map(|&byte| [byte, byte]).collect();
+        image.header.bit_depth = 16;
+        image
+    }
+
+    #[test]
+    fn test_sequential_round_trip() {
+        let mut image = testing_image();
+        embed(
+            &mut image,
+            b"hidden in plain sight",
+            &options(None, Strategy::Replace),
+        )
+        .unwrap();
+        assert_eq!(extract(&image, None).unwrap(), b"hidden in plain sight");
+    }
+
+    #[test]
+    fn test_keyed_round_trip() {
+        let original = testing_image();
+        let mut image = original.clone();
+        embed(
+            &mut image,
+            b"scattered",
+            &options(Some("secret"), Strategy::Replace),
+        )
+        .unwrap();
+        assert_eq!(extract(&image, Some("secret")).unwrap(), b"scattered");
+        assert_ne!(
+            extract(&image, Some("other")).ok(),
+            Some(b"scattered".to_vec())
+        );
+
+        // the changed samples are not bunched up at the top of the image
+        let changed: Vec<usize> = (0..image.sample_count())
+            .filter(|&index| image.sample(index) != original.sample(index))
+            .collect();
+        assert!(*changed.last().unwrap() > image.sample_count() / 2);
+    }
+
+    #[test]
+    fn test_capacity_exceeded() {
+        let mut image = testing_image();
+        let data = vec![0u8; plan(&image, 0, &LsbOptions::default()).unwrap().capacity() + 1];
+        assert!(matches!(
+            embed(&mut image, &data, &options(None, Strategy::Replace)),
+            Err(Error::CapacityExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_palette_images() {
+        let contents = std::fs::read("assets/catgurl.png").unwrap();
+        let mut image = RawImage::from_png(&Png::try_from(contents.as_slice()).unwrap()).unwrap();
+        assert!(matches!(
+            embed(&mut image, b"x", &options(None, Strategy::Replace)),
+            Err(Error::UnsupportedImage(_))
+        ));
+    }
+
+    #[test]
+    fn test_strategies_round_trip() {
+        let message = b"fewer changes, same message".repeat(20);
+        for strategy in [Strategy::Replace, Strategy::Matching, Strategy::Matrix] {
+            let mut image = testing_image();
+            embed(&mut image, &message, &options(Some("key"), strategy)).unwrap();
+            assert_eq!(extract(&image, Some("key")).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_matching_changes_by_one() {
+        let original = testing_image();
+        let mut image = original.clone();
+        embed(&mut image, &[0xa5; 500], &options(None, Strategy::Matching)).unwrap();
+
+        for index in 0..image.sample_count() {
+            let difference = image.sample(index) as i32 - original.sample(index) as i32;
+            assert!(difference.abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_matrix_changes_fewer_samples() {
+        let message: Vec<u8> = (0..2000).map(|i| (i * 7919 % 251) as u8).collect();
+        let original = testing_image();
+        let changed = |strategy| {
+            let mut image = original.clone();
+            embed(&mut image, &message, &options(Some("key"), strategy)).unwrap();
+            (0..image.sample_count())
+                .filter(|&index| image.sample(index) != original.sample(index))
+                .count()
+        };
+
+        let plan = plan(&original, message.len(), &options(None, Strategy::Matrix)).unwrap();
+        assert!(plan.bits_per_block > 1);
+        assert!(changed(Strategy::Matrix) * 2 < changed(Strategy::Replace));
+        assert!(plan.expected_changes() * 2.0 < message.len() as f64 * 8.0 / 2.0);
+    }
+
+    #[test]
+    fn test_adaptive_round_trip() {
+        let message = b"hidden where it is busy".repeat(30);
+        for strategy in [Strategy::Replace, Strategy::Matrix] {
+            let options = LsbOptions {
+                adaptive: true,
+                ..options(Some("key"), strategy)
+            };
+            let mut image = testing_image();
+            embed(&mut image, &message, &options).unwrap();
+            assert_eq!(extract(&image, Some("key")).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_prefers_texture() {
+        let original = testing_image();
+        let options = LsbOptions {
+            adaptive: true,
+            ..options(Some("key"), Strategy::Replace)
+        };
+        let texture = texture(&original, 1);
+        let mut image = original.clone();
+        embed(&mut image, &[0x5a; 2000], &options).unwrap();
+
+        let changed: Vec<u32> = (0..image.sample_count())
+            .filter(|&index| image.sample(index) != original.sample(index))
+            .map(|index| texture[index])
+            .collect();
+        let average = |values: &[u32]| {
+            values.iter().map(|&value| value as f64).sum::<f64>() / values.len() as f64
+        };
+        assert!(average(&changed) > 2.0 * average(&texture));
+        assert!(
+            plan(&original, 0, &options).unwrap().capacity()
+                < plan(&original, 0, &LsbOptions::default())
+                    .unwrap()
+                    .capacity()
+        );
+    }
+
+    #[test]
+    fn test_texture_ignores_low_bits() {
+        let original = testing_image();
+        let mut image = original.clone();
+        for index in 0..image.sample_count() {
+            image.set_sample(index, image.sample(index) ^ 0b11);
+        }
+        assert_eq!(texture(&image, 2), texture(&original, 2));
+    }
+
+    #[test]
+    fn test_adaptive_rejects_matching() {
+        let options = LsbOptions {
+            adaptive: true,
+            ..options(None, Strategy::Matching)
+        };
+        assert!(matches!(
+            plan(&testing_image(), 10, &options),
+            Err(Error::IncompatibleOptions(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let options = LsbOptions {
+            key: Some("key".to_string()),
+            strategy: Strategy::Matrix,
+            adaptive: true,
+            channels: Channels::from_str("gA").unwrap(),
+            bits_per_sample: 8,
+            transparent_pixels: TransparentPixels::Skip,
+        };
+        let decoded = LsbOptions::from_header(options.header(), Some("key")).unwrap();
+        assert_eq!(decoded.header(), options.header());
+        assert_eq!(decoded.channels.to_string(), "ga");
+        assert!(LsbOptions::from_header(0, None).is_err());
+    }
+
+    #[test]
+    fn test_bits_per_sample_and_channels() {
+        let original = testing_image();
+        let message = b"two bits at a time in green and blue".repeat(10);
+        for strategy in [Strategy::Replace, Strategy::Matching] {
+            let options = LsbOptions {
+                channels: Channels::from_str("gb").unwrap(),
+                bits_per_sample: 2,
+                ..options(Some("key"), strategy)
+            };
+            let mut image = original.clone();
+            embed(&mut image, &message, &options).unwrap();
+            assert_eq!(extract(&image, Some("key")).unwrap(), message);
+
+            let header = header_positions(&image, Some("key")).unwrap();
+            for index in 0..image.sample_count() {
+                let difference = image.sample(index).abs_diff(original.sample(index));
+                assert!(difference <= 3);
+                // red is only ever touched by the header
+                if index % 3 == 0 && !header.contains(&index) {
+                    assert_eq!(difference, 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sixteen_bit_low_byte() {
+        let mut image = testing_16_bit_image();
+        let options = LsbOptions {
+            bits_per_sample: 8,
+            ..options(Some("key"), Strategy::Replace)
+        };
+        let message = vec![0x3c; 20000];
+        embed(&mut image, &message, &options).unwrap();
+        assert_eq!(extract(&image, Some("key")).unwrap(), message);
+
+        let too_many = LsbOptions {
+            bits_per_sample: 5,
+            ..Default::default()
+        };
+        assert!(plan(&testing_image(), 1, &too_many).is_err());
+    }
+
+    #[test]
+    fn test_transparent_pixels() {
+        let original = testing_rgba_image();
+        let message = vec![0x77; 1000];
+
+        for mode in [TransparentPixels::Skip, TransparentPixels::Only] {
+            let options = LsbOptions {
+                transparent_pixels: mode,
+                bits_per_sample: if mode == TransparentPixels::Only {
+                    8
+                } else {
+                    1
+                },
+                ..options(Some("key"), Strategy::Replace)
+            };
+            let mut image = original.clone();
+            let plan = embed(&mut image, &message, &options).unwrap();
+            assert_eq!(extract(&image, Some("key")).unwrap(), message);
+
+            let header = header_positions(&image, Some("key")).unwrap();
+            for index in 0..image.sample_count() {
+                if image.sample(index) == original.sample(index) || header.contains(&index) {
+                    continue;
+                }
+                assert!(!is_alpha(&image, index));
+                assert_eq!(
+                    mode == TransparentPixels::Only,
+                    is_transparent(&image, index)
+                );
+            }
+            if mode == TransparentPixels::Only {
+                assert!(plan.capacity() > plan.samples_available / 2);
+            }
+        }
+
+        let with_alpha = LsbOptions {
+            channels: Channels::from_str("rgba").unwrap(),
+            transparent_pixels: TransparentPixels::Skip,
+            ..Default::default()
+        };
+        assert!(matches!(
+            plan(&original, 1, &with_alpha),
+            Err(Error::IncompatibleOptions(_))
+        ));
+    }
+}
@@ -1,11 +1,29 @@
-mod cli;
-mod chunk_type;
+mod apng;
+mod bits;
 mod chunk;
+mod chunk_type;
+mod cli;
+mod commands;
+mod config;
+mod deniable;
+mod diff;
+mod dump;
+mod encryption;
+mod entries;
 mod errors;
+mod image;
+mod keys;
+mod known_chunk;
+mod lsb;
+mod manifest;
+mod optimize;
+mod output;
+mod palette;
+mod payload;
 mod png;
-mod commands;
+mod signing;
+mod transplant;
 
-fn main() -> anyhow::Result<()>{
-    cli::parse()?;
-    Ok(())
+fn main() -> std::process::ExitCode {
+    cli::parse()
 }
@@ -0,0 +1,235 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, errors::Error, png::Png};
+
+pub const MANIFEST_VERSION: u32 = 1;
+// Small binary data stays readable as hex, larger data is base64.
+const HEX_MAX: usize = 64;
+// With a data directory, binary data from this size up goes to a file.
+const EXTERNAL_MIN: usize = 1024;
+
+// A PNG as a list of chunks whose lengths and CRCs are filled in when it is
+// built, unless `crc` is given to craft a broken file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub manifest_version: u32,
+    // hex, the standard PNG signature when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    pub chunks: Vec<ManifestChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestChunk {
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+    #[serde(flatten)]
+    pub data: Data,
+    // hex, overrides the computed CRC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Data {
+    Text(String),
+    Hex(String),
+    Base64(String),
+    // relative to the manifest
+    File(PathBuf),
+}
+
+impl Data {
+    fn choose(data: &[u8]) -> Data {
+        match std::str::from_utf8(data) {
+            Ok(text)
+                if text
+                    .chars()
+                    .all(|c| !c.is_control() || "\0\n\t".contains(c)) =>
+            {
+                Data::Text(text.to_string())
+            }
+            _ if data.len() <= HEX_MAX => Data::Hex(hex::encode(data)),
+            _ => Data::Base64(STANDARD.encode(data)),
+        }
+    }
+}
+
+impl Manifest {
+    // With a `data_dir`, large binary chunks are listed as files named after
+    // their position and type, returned alongside for the caller to write.
+    pub fn from_png(png: &Png, data_dir: Option<&Path>) -> (Manifest, Vec<(PathBuf, Vec<u8>)>) {
+        let mut files = Vec::new();
+        let chunks = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let mut data = Data::choose(chunk.data());
+                if let (Some(dir), Data::Base64(_)) = (data_dir, &data) {
+                    if chunk.data().len() >= EXTERNAL_MIN {
+                        let path = dir.join(format!("{:03}-{}.bin", index, chunk.chunk_type()));
+                        files.push((path.clone(), chunk.data().to_vec()));
+                        data = Data::File(path);
+                    }
+                }
+                ManifestChunk {
+                    chunk_type: chunk.chunk_type().to_string(),
+                    data,
+                    crc: None,
+                }
+            })
+            .collect();
+
+        let manifest = Manifest {
+            manifest_version: MANIFEST_VERSION,
+            signature: None,
+            chunks,
+        };
+        (manifest, files)
+    }
+
+    // File paths are read relative to `base_dir`.
+    pub fn build(&self, base_dir: &Path) -> anyhow::Result<Vec<u8>> {
+        if self.manifest_version != MANIFEST_VERSION {
+            return Err(Error::InvalidManifest(format!(
+                "manifest version {} is not supported",
+                self.manifest_version
+            ))
+            .into());
+        }
+
+        let mut bytes = match &self.signature {
+            Some(signature) => decode_hex("signature", signature)?,
+            None => Png::STANDARD_HEADER.to_vec(),
+        };
+        for (index, entry) in self.chunks.iter().enumerate() {
+            let invalid = |reason: String| {
+                Error::InvalidManifest(format!(
+                    "chunk {} ({}): {}",
+                    index, entry.chunk_type, reason
+                ))
+            };
+            let chunk_type =
+                ChunkType::from_str(&entry.chunk_type).map_err(|err| invalid(err.to_string()))?;
+            let data = match &entry.data {
+                Data::Text(text) => text.as_bytes().to_vec(),
+                Data::Hex(hex) => hex::decode(hex).map_err(|err| invalid(err.to_string()))?,
+                Data::Base64(base64) => STANDARD
+                    .decode(base64)
+                    .map_err(|err| invalid(err.to_string()))?,
+                Data::File(path) => {
+                    // manifests get shared, so they may only read files next to them
+                    if !inside_dir(path) {
+                        return Err(invalid(format!(
+                            "file `{}` must be a relative path inside the manifest's directory",
+                            path.display()
+                        ))
+                        .into());
+                    }
+                    fs::read(base_dir.join(path))?
+                }
+            };
+
+            let mut chunk = Chunk::new(chunk_type, data).as_bytes();
+            if let Some(crc) = &entry.crc {
+                let crc: [u8; 4] = decode_hex("crc", crc)?
+                    .try_into()
+                    .map_err(|_| invalid("crc must be 4 bytes".to_string()))?;
+                let end = chunk.len();
+                chunk[end - 4..].copy_from_slice(&crc);
+            }
+            bytes.extend(chunk);
+        }
+        Ok(bytes)
+    }
+}
+
+// Whether a path relative to the manifest stays in the manifest's directory.
+pub fn inside_dir(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value).map_err(|err| Error::InvalidManifest(format!("{}: {}", field, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let contents = std::fs::read("assets/catgurl.png").unwrap();
+        let png = Png::try_from(contents.as_slice()).unwrap();
+
+        let (manifest, files) = Manifest::from_png(&png, None);
+        assert!(files.is_empty());
+        assert_eq!(
+            manifest.chunks[0].data,
+            Data::Hex("00000168000001df0803000000".into())
+        );
+        assert_eq!(manifest.chunks[6].data, Data::Text(String::new()));
+
+        let json = serde_json::to_string(&manifest).unwrap();
+        let manifest: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(manifest.build(Path::new(".")).unwrap(), contents);
+    }
+
+    #[test]
+    fn test_crafted_file() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{
+                "manifest_version": 1,
+                "chunks": [
+                    {"type": "tEXt", "text": "Comment\u0000crafted"},
+                    {"type": "ruSt", "base64": "AAEC", "crc": "deadbeef"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let bytes = manifest.build(Path::new(".")).unwrap();
+        assert!(bytes.ends_with(&[0, 1, 2, 0xde, 0xad, 0xbe, 0xef]));
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"manifest_version": 1, "chunks": [{"type": "Rust", "hex": ""}]}"#,
+        )
+        .unwrap();
+        assert!(manifest.build(Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_file_paths_stay_inside() {
+        let build = |path: &str| {
+            Manifest {
+                manifest_version: MANIFEST_VERSION,
+                signature: None,
+                chunks: vec![ManifestChunk {
+                    chunk_type: "ruSt".to_string(),
+                    data: Data::File(PathBuf::from(path)),
+                    crc: None,
+                }],
+            }
+            .build(Path::new("assets"))
+        };
+        assert!(build("./catgurl.png").is_ok());
+        for path in ["../Cargo.toml", "sub/../../Cargo.toml", "/etc/passwd"] {
+            let err = build(path).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(Error::InvalidManifest(_))
+            ));
+        }
+    }
+}
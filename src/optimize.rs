@@ -0,0 +1,191 @@
+use std::fmt::Display;
+
+use flate2::Compression;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    errors::Error,
+    image::{RawImage, RowFilters},
+    known_chunk::{ColorType, ImageHeader, KnownChunk, Transparency},
+    png::Png,
+};
+
+// Whole-image filter choices, the last one picking per row.
+const FILTER_CHOICES: [(&str, &[u8]); 6] = [
+    ("none", &[0]),
+    ("sub", &[1]),
+    ("up", &[2]),
+    ("average", &[3]),
+    ("paeth", &[4]),
+    ("adaptive", &[0, 1, 2, 3, 4]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Optimization {
+    // None when the original zlib stream was already the smallest
+    pub filter: Option<&'static str>,
+    pub idat_chunks: usize,
+    pub idat_before: usize,
+    pub idat_after: usize,
+    pub dropped: Vec<String>,
+}
+
+impl Display for Optimization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IDAT {} -> {} bytes ", self.idat_before, self.idat_after)?;
+        match self.filter {
+            Some(filter) => write!(f, "with {} filtering at zlib level 9", filter)?,
+            None => write!(f, "keeping the original zlib stream")?,
+        }
+        if self.idat_chunks > 1 {
+            write!(f, ", {} IDAT chunks merged into 1", self.idat_chunks)?;
+        }
+        write!(f, ", ")?;
+        if self.dropped.is_empty() {
+            write!(f, "no redundant chunks")
+        } else {
+            write!(f, "dropped {}", self.dropped.join(", "))
+        }
+    }
+}
+
+// Recompresses the image data with whichever filter gives the smallest stream,
+// stores it in a single IDAT and drops chunks that carry nothing. Chunks of
+// type `keep` are never dropped.
+pub fn optimize(png: &mut Png, keep: Option<&ChunkType>) -> Result<Optimization, Error> {
+    let idats = png
+        .chunk_by_type("IDAT")
+        .ok_or(Error::ChunkTypeNotFound("IDAT".to_string()))?;
+    let idat_chunks = idats.len();
+    let original: Vec<u8> = idats
+        .iter()
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    let idat_before = original.len();
+
+    let image = RawImage::from_png(png)?;
+    let (filter, smallest) = FILTER_CHOICES
+        .iter()
+        .map(|(name, filter_types)| {
+            (
+                *name,
+                image.encode_with(RowFilters::Best(filter_types), Compression::best()),
+            )
+        })
+        .min_by_key(|(_, data)| data.len())
+        .unwrap();
+    let (filter, data) = if smallest.len() < original.len() {
+        (Some(filter), smallest)
+    } else {
+        (None, original)
+    };
+
+    let idat_after = data.len();
+    png.replace_image_data(vec![Chunk::new(
+        ChunkType::try_from(*b"IDAT").unwrap(),
+        data,
+    )])?;
+
+    Ok(Optimization {
+        filter,
+        idat_chunks,
+        idat_before,
+        idat_after,
+        dropped: drop_redundant(png, keep)?,
+    })
+}
+
+// Empty and duplicated ancillary chunks, and ones restating the defaults.
+fn drop_redundant(png: &mut Png, keep: Option<&ChunkType>) -> Result<Vec<String>, Error> {
+    let header = png.image_header()?;
+    let mut kept: Vec<Chunk> = Vec::new();
+    let mut dropped = Vec::new();
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        let redundant = !chunk_type.is_critical()
+            && keep != Some(chunk_type)
+            && (chunk.length() == 0
+                || kept.iter().any(|earlier| {
+                    earlier.chunk_type() == chunk_type && earlier.data() == chunk.data()
+                })
+                || matches!(
+                    chunk.parse_known_with(Some(header.color_type)),
+                    Ok(known) if states_defaults(&known, &header)
+                ));
+        if redundant {
+            dropped.push(chunk_type.to_string());
+        } else {
+            kept.push(chunk.clone());
+        }
+    }
+
+    *png = Png::from_chunks(kept);
+    Ok(dropped)
+}
+
+fn states_defaults(known: &KnownChunk, header: &ImageHeader) -> bool {
+    match known {
+        // every palette entry opaque
+        KnownChunk::Trns(Transparency::Palette(alphas)) => {
+            alphas.iter().all(|&alpha| alpha == u8::MAX)
+        }
+        // every bit significant
+        KnownChunk::Sbit(bits) => {
+            let depth = match header.color_type {
+                ColorType::Indexed => 8,
+                _ => header.bit_depth,
+            };
+            bits.iter().all(|&bits| bits == depth)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png(path: &str) -> Png {
+        let contents = std::fs::read(path).unwrap();
+        Png::try_from(contents.as_slice()).unwrap()
+    }
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_keeps_pixels_and_merges_idat() {
+        let mut png = testing_png("assets/catgurl.png");
+        let before = RawImage::from_png(&png).unwrap();
+        let size = png.as_bytes().len();
+
+        let optimization = optimize(&mut png, None).unwrap();
+        assert_eq!(png.chunk_by_type("IDAT").unwrap().len(), 1);
+        assert!(optimization.idat_after <= optimization.idat_before);
+        assert!(png.as_bytes().len() <= size);
+        assert_eq!(RawImage::from_png(&png).unwrap().data, before.data);
+    }
+
+    #[test]
+    fn test_drops_redundant_chunks() {
+        let mut png = testing_png("assets/catgurl.png");
+        let text = chunk("tEXt", b"Comment\0twice");
+        png.insert_between(text.clone());
+        png.insert_between(text);
+        png.insert_between(chunk("ruSt", b""));
+        png.insert_between(chunk("sBIT", &[8, 8, 8]));
+        png.insert_between(chunk("STXT", b""));
+
+        let stxt = ChunkType::from_str("STXT").unwrap();
+        let optimization = optimize(&mut png, Some(&stxt)).unwrap();
+        let mut dropped = optimization.dropped.clone();
+        dropped.sort();
+        assert_eq!(dropped, ["ruSt", "sBIT", "tEXt"]);
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().len(), 1);
+        assert!(png.chunk_by_type("STXT").is_some());
+    }
+}
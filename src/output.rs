@@ -0,0 +1,197 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::errors::Error;
+
+// Bumped whenever a field is renamed, removed or changes meaning. New fields
+// can appear without a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+// What a command produced: lines of text for people, and the `result` object
+// of the JSON envelope for scripts.
+pub trait Report: Serialize {
+    fn lines(&self) -> Vec<String>;
+
+    // Printed to stderr as text; reports that have any also carry them in
+    // their `result`.
+    fn warnings(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+// For commands that only describe what they did.
+#[derive(Debug, Default, Serialize)]
+pub struct Notes {
+    pub report: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl Report for Notes {
+    fn lines(&self) -> Vec<String> {
+        self.report.clone()
+    }
+
+    fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+}
+
+impl From<Vec<String>> for Notes {
+    fn from(report: Vec<String>) -> Self {
+        Notes {
+            report,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorObject {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl From<&Error> for ErrorObject {
+    fn from(err: &Error) -> Self {
+        ErrorObject {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<&anyhow::Error> for ErrorObject {
+    fn from(err: &anyhow::Error) -> Self {
+        ErrorObject {
+            code: error_code(err),
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T: Serialize> {
+    schema_version: u32,
+    command: &'a str,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorObject>,
+}
+
+pub fn print<T: Report>(format: Format, command: &str, report: &T) -> anyhow::Result<()> {
+    match format {
+        Format::Text => {
+            for warning in report.warnings() {
+                eprintln!("Warning: {}", warning);
+            }
+            for line in report.lines() {
+                println!("{}", line);
+            }
+        }
+        Format::Json => {
+            let envelope = Envelope {
+                schema_version: SCHEMA_VERSION,
+                command,
+                ok: true,
+                result: Some(report),
+                error: None,
+            };
+            println!("{}", serde_json::to_string(&envelope)?);
+        }
+    }
+    Ok(())
+}
+
+// Errors go to stdout too in JSON mode, so a script only reads one stream.
+pub fn print_error(command: &str, err: &anyhow::Error) {
+    let envelope: Envelope<Notes> = Envelope {
+        schema_version: SCHEMA_VERSION,
+        command,
+        ok: false,
+        result: None,
+        error: Some(ErrorObject::from(err)),
+    };
+    println!("{}", serde_json::to_string(&envelope).unwrap());
+}
+
+// Our own errors carry their variant's code, anything else is classed by
+// type.
+pub fn error_code(err: &anyhow::Error) -> &'static str {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return err.code();
+    }
+    if err.downcast_ref::<std::io::Error>().is_some() {
+        return "io";
+    }
+    if err.downcast_ref::<std::string::FromUtf8Error>().is_some() {
+        return "invalid_utf8";
+    }
+    "other"
+}
+
+// Exit status for a failed command; 1 when the failure has no class of its own.
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    if let Some(err) = err.downcast_ref::<Error>() {
+        return err.exit_code();
+    }
+    if err.downcast_ref::<std::io::Error>().is_some() {
+        return 3;
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let err = anyhow::Error::from(Error::CrcMismatch);
+        assert_eq!(error_code(&err), "crc_mismatch");
+
+        let err = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(error_code(&err), "io");
+
+        assert_eq!(error_code(&anyhow::anyhow!("something else")), "other");
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let err = anyhow::Error::from(Error::CrcMismatch);
+        assert_eq!(exit_code(&err), 6);
+
+        let err = anyhow::Error::from(Error::NoHiddenData).context("while decoding");
+        assert_eq!(exit_code(&err), 4);
+
+        let err = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(exit_code(&err), 3);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("something else")), 1);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let notes = Notes::from(vec!["done".to_string()]);
+        let envelope = Envelope {
+            schema_version: SCHEMA_VERSION,
+            command: "encode",
+            ok: true,
+            result: Some(&notes),
+            error: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&envelope).unwrap(),
+            r#"{"schema_version":1,"command":"encode","ok":true,"result":{"report":["done"]}}"#
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use crate::{
+    errors::Error,
+    image::{Preservation, RawImage},
+    known_chunk::{Background, ColorType, KnownChunk, PaletteEntry, Transparency},
+    lsb::{self, LsbOptions, Plan},
+    png::Png,
+};
+
+// EzStego-style embedding: the palette is reordered so neighbouring entries
+// look alike, after which the low bit of an index only ever swaps a colour for
+// a similar one and the usual LSB strategies can run on the indices.
+pub fn embed(
+    png: &mut Png,
+    data: &[u8],
+    options: &LsbOptions,
+) -> Result<(Plan, Preservation), Error> {
+    let mut image = indexed_image(png)?;
+    sort_palette(png, &mut image)?;
+    let plan = lsb::embed_samples(&mut image, data, options)?;
+    update_histogram(png, &image)?;
+    let preservation = image.write_to(png)?;
+    Ok((plan, preservation))
+}
+
+// Sorting can add a palette entry and moves texture around, so the plan is
+// made on a sorted copy.
+pub fn plan(png: &Png, length: usize, options: &LsbOptions) -> Result<Plan, Error> {
+    let mut png = Png::from_chunks(png.chunks().to_vec());
+    let mut image = indexed_image(&png)?;
+    sort_palette(&mut png, &mut image)?;
+    lsb::plan_samples(&image, length, options)
+}
+
+pub fn extract(png: &Png, key: Option<&str>) -> Result<Vec<u8>, Error> {
+    lsb::extract_samples(&indexed_image(png)?, key)
+}
+
+fn indexed_image(png: &Png) -> Result<RawImage, Error> {
+    let image = RawImage::from_png(png)?;
+    if image.header.color_type != ColorType::Indexed {
+        return Err(Error::UnsupportedImage(
+            "palette embedding needs a palette image, use --method lsb",
+        ));
+    }
+    Ok(image)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Colour {
+    entry: PaletteEntry,
+    alpha: u8,
+    frequency: u16,
+}
+
+impl Colour {
+    fn luminance(&self) -> u32 {
+        299 * self.entry.red as u32 + 587 * self.entry.green as u32 + 114 * self.entry.blue as u32
+    }
+
+    fn distance(&self, other: &Colour) -> u32 {
+        [
+            (self.entry.red, other.entry.red),
+            (self.entry.green, other.entry.green),
+            (self.entry.blue, other.entry.blue),
+            (self.alpha, other.alpha),
+        ]
+        .iter()
+        .map(|&(a, b)| (a as i32 - b as i32).pow(2) as u32)
+        .sum()
+    }
+}
+
+fn sort_palette(png: &mut Png, image: &mut RawImage) -> Result<(), Error> {
+    // fdAT frames index the same palette but only IDAT is remapped here
+    if png.chunk_by_type("acTL").is_some() || png.chunk_by_type("fdAT").is_some() {
+        return Err(Error::UnsupportedImage(
+            "palette embedding cannot reorder the palette of an animated PNG, use --method lsb",
+        ));
+    }
+    let entries = png
+        .palette()?
+        .ok_or(Error::ChunkTypeNotFound("PLTE".to_string()))?;
+    let alphas = match known_chunk(png, "tRNS")? {
+        Some(KnownChunk::Trns(Transparency::Palette(alphas))) => Some(alphas),
+        _ => None,
+    };
+    let histogram = match known_chunk(png, "hIST")? {
+        Some(KnownChunk::Hist(frequencies)) => Some(frequencies),
+        _ => None,
+    };
+    let background = match known_chunk(png, "bKGD")? {
+        Some(KnownChunk::Bkgd(Background::PaletteIndex(index))) => Some(index),
+        _ => None,
+    };
+
+    let mut colours: Vec<Colour> = entries
+        .iter()
+        .enumerate()
+        .map(|(index, &entry)| Colour {
+            entry,
+            alpha: alphas
+                .as_ref()
+                .and_then(|alphas| alphas.get(index).copied())
+                .unwrap_or(u8::MAX),
+            frequency: histogram
+                .as_ref()
+                .and_then(|histogram| histogram.get(index).copied())
+                .unwrap_or(0),
+        })
+        .collect();
+
+    // every index needs a partner differing only in the low bit, so an odd
+    // palette gets a copy of its last colour when the bit depth has room
+    if colours.len() % 2 == 1 && colours.len() < 1 << image.header.bit_depth {
+        let mut copy = *colours.last().unwrap();
+        copy.frequency = 0;
+        colours.push(copy);
+    }
+
+    // Greedy nearest-neighbour chain starting from the darkest colour.
+    let mut remaining: Vec<usize> = (0..colours.len()).collect();
+    let darkest = (0..remaining.len())
+        .min_by_key(|&i| colours[remaining[i]].luminance())
+        .unwrap();
+    let mut order = vec![remaining.remove(darkest)];
+    while !remaining.is_empty() {
+        let last = &colours[*order.last().unwrap()];
+        let nearest = (0..remaining.len())
+            .min_by_key(|&i| last.distance(&colours[remaining[i]]))
+            .unwrap();
+        order.push(remaining.remove(nearest));
+    }
+
+    let mut new_index = vec![0u16; colours.len()];
+    for (position, &old) in order.iter().enumerate() {
+        new_index[old] = position as u16;
+    }
+    for index in 0..image.sample_count() {
+        let old = image.sample(index) as usize;
+        let new = *new_index.get(old).ok_or(Error::MalformedChunk {
+            chunk_type: "IDAT",
+            reason: "pixel refers to a colour outside the palette",
+        })?;
+        image.set_sample(index, new);
+    }
+
+    let colours: Vec<Colour> = order.iter().map(|&old| colours[old]).collect();
+    png.replace_chunk(
+        KnownChunk::Plte(colours.iter().map(|colour| colour.entry).collect()).to_chunk(),
+    );
+    if alphas.is_some() {
+        let mut alphas: Vec<u8> = colours.iter().map(|colour| colour.alpha).collect();
+        while alphas.len() > 1 && alphas.last() == Some(&u8::MAX) {
+            alphas.pop();
+        }
+        png.replace_chunk(KnownChunk::Trns(Transparency::Palette(alphas)).to_chunk());
+    }
+    if histogram.is_some() {
+        let frequencies = colours.iter().map(|colour| colour.frequency).collect();
+        png.replace_chunk(KnownChunk::Hist(frequencies).to_chunk());
+    }
+    if let Some(&index) = background.and_then(|old| new_index.get(old as usize)) {
+        png.replace_chunk(KnownChunk::Bkgd(Background::PaletteIndex(index as u8)).to_chunk());
+    }
+    Ok(())
+}
+
+// hIST holds relative frequencies scaled to fit 16 bits, and embedding moves
+// pixels between colours, so it is recounted.
+fn update_histogram(png: &mut Png, image: &RawImage) -> Result<(), Error> {
+    let Some(KnownChunk::Hist(frequencies)) = known_chunk(png, "hIST")? else {
+        return Ok(());
+    };
+
+    let mut counts = vec![0u64; frequencies.len()];
+    for index in 0..image.sample_count() {
+        if let Some(count) = counts.get_mut(image.sample(index) as usize) {
+            *count += 1;
+        }
+    }
+    let most = counts.iter().copied().max().unwrap_or(0).max(1);
+    let frequencies = counts
+        .iter()
+        .map(|&count| match count {
+            0 => 0,
+            count => (count * u16::MAX as u64 / most).max(1) as u16,
+        })
+        .collect();
+    png.replace_chunk(KnownChunk::Hist(frequencies).to_chunk());
+    Ok(())
+}
+
+fn known_chunk(png: &Png, chunk_type: &str) -> Result<Option<KnownChunk>, Error> {
+    match png.chunk_by_type(chunk_type) {
+        Some(chunks) => Ok(Some(chunks[0].parse_known_with(Some(ColorType::Indexed))?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsb::Strategy;
+
+    fn testing_png(path: &str) -> Png {
+        let contents = std::fs::read(path).unwrap();
+        Png::try_from(contents.as_slice()).unwrap()
+    }
+
+    fn colours(png: &Png) -> Vec<PaletteEntry> {
+        let palette = png.palette().unwrap().unwrap();
+        let image = RawImage::from_png(png).unwrap();
+        (0..image.sample_count())
+            .map(|index| palette[image.sample(index) as usize])
+            .collect()
+    }
+
+    fn distance(a: &PaletteEntry, b: &PaletteEntry) -> i32 {
+        [(a.red, b.red), (a.green, b.green), (a.blue, b.blue)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).abs())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for path in ["assets/catgurl.png", "assets/animegurl.png"] {
+            let mut png = testing_png(path);
+            let options = LsbOptions {
+                key: Some("key".to_string()),
+                strategy: Strategy::Replace,
+                ..Default::default()
+            };
+            embed(&mut png, b"between the colours", &options).unwrap();
+            assert_eq!(extract(&png, Some("key")).unwrap(), b"between the colours");
+        }
+    }
+
+    #[test]
+    fn test_sorting_keeps_the_picture() {
+        let original = testing_png("assets/catgurl.png");
+        let mut png = testing_png("assets/catgurl.png");
+        let mut image = RawImage::from_png(&png).unwrap();
+        sort_palette(&mut png, &mut image).unwrap();
+        image.write_to(&mut png).unwrap();
+
+        assert_eq!(colours(&png), colours(&original));
+    }
+
+    #[test]
+    fn test_changes_are_to_similar_colours() {
+        let original = testing_png("assets/catgurl.png");
+        let mut png = testing_png("assets/catgurl.png");
+        let message = vec![0x5a; 2000];
+        embed(&mut png, &message, &LsbOptions::default()).unwrap();
+
+        let before = colours(&original);
+        let after = colours(&png);
+        let changed: Vec<i32> = before
+            .iter()
+            .zip(&after)
+            .map(|(a, b)| distance(a, b))
+            .filter(|&distance| distance > 0)
+            .collect();
+        assert!(!changed.is_empty());
+        let average = changed.iter().sum::<i32>() as f64 / changed.len() as f64;
+        assert!(average < 32.0, "average colour change {}", average);
+    }
+
+    #[test]
+    fn test_transparency_follows_palette() {
+        let mut png = testing_png("assets/catgurl.png");
+        let entries = png.palette().unwrap().unwrap();
+        let alphas: Vec<u8> = (0..entries.len()).map(|i| (i * 5 % 256) as u8).collect();
+        png.replace_chunk(KnownChunk::Trns(Transparency::Palette(alphas.clone())).to_chunk());
+
+        let alpha_of = |png: &Png| -> Vec<(PaletteEntry, u8)> {
+            let palette = png.palette().unwrap().unwrap();
+            let Some(KnownChunk::Trns(Transparency::Palette(alphas))) =
+                known_chunk(png, "tRNS").unwrap()
+            else {
+                panic!("tRNS missing");
+            };
+            let mut pairs: Vec<(PaletteEntry, u8)> = palette
+                .iter()
+                .enumerate()
+                .map(|(i, &entry)| (entry, alphas.get(i).copied().unwrap_or(u8::MAX)))
+                .collect();
+            pairs.sort_by_key(|(entry, alpha)| (entry.red, entry.green, entry.blue, *alpha));
+            pairs.dedup();
+            pairs
+        };
+
+        let before = alpha_of(&png);
+        let mut image = RawImage::from_png(&png).unwrap();
+        sort_palette(&mut png, &mut image).unwrap();
+        assert_eq!(alpha_of(&png), before);
+    }
+
+    #[test]
+    fn test_background_follows_palette() {
+        let mut png = testing_png("assets/catgurl.png");
+        let entries = png.palette().unwrap().unwrap();
+        let background = entries.len() as u8 / 2;
+        png.replace_chunk(KnownChunk::Bkgd(Background::PaletteIndex(background)).to_chunk());
+
+        let mut image = RawImage::from_png(&png).unwrap();
+        sort_palette(&mut png, &mut image).unwrap();
+        let Some(KnownChunk::Bkgd(Background::PaletteIndex(index))) =
+            known_chunk(&png, "bKGD").unwrap()
+        else {
+            panic!("bKGD missing");
+        };
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(palette[index as usize], entries[background as usize]);
+    }
+
+    #[test]
+    fn test_rejects_animated_images() {
+        let mut png = testing_png("assets/catgurl.png");
+        png.insert_between(
+            KnownChunk::Actl {
+                num_frames: 1,
+                num_plays: 0,
+            }
+            .to_chunk(),
+        );
+
+        assert!(matches!(
+            embed(&mut png, b"x", &LsbOptions::default()),
+            Err(Error::UnsupportedImage(_))
+        ));
+        assert!(plan(&png, 1, &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_histogram_is_recounted() {
+        let mut png = testing_png("assets/catgurl.png");
+        let entries = png.palette().unwrap().unwrap();
+        png.replace_chunk(KnownChunk::Hist(vec![1; entries.len()]).to_chunk());
+
+        embed(&mut png, b"counted", &LsbOptions::default()).unwrap();
+        let Some(KnownChunk::Hist(frequencies)) = known_chunk(&png, "hIST").unwrap() else {
+            panic!("hIST missing");
+        };
+        assert!(frequencies.len() >= entries.len());
+        assert_eq!(frequencies.iter().max(), Some(&u16::MAX));
+    }
+
+    #[test]
+    fn test_rejects_true_colour_images() {
+        let mut png = testing_png("assets/ryanGosling.png");
+        assert!(matches!(
+            embed(&mut png, b"x", &LsbOptions::default()),
+            Err(Error::UnsupportedImage(_))
+        ));
+    }
+}
@@ -0,0 +1,657 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Read, Write},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    encryption::{Encryption, WrappedKey},
+    errors::Error,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadSignature {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Text,
+    Binary,
+}
+
+impl ContentType {
+    fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(ContentType::Text),
+            1 => Ok(ContentType::Binary),
+            _ => Err(Error::MalformedPayload("unknown content type")),
+        }
+    }
+
+    fn as_byte(self) -> u8 {
+        match self {
+            ContentType::Text => 0,
+            ContentType::Binary => 1,
+        }
+    }
+}
+
+// Reassembled data, or why it couldn't be read, and the positions it was
+// joined from.
+pub type Joined = (Vec<usize>, Result<Vec<u8>, Error>);
+
+// One piece of a frame too large for a single chunk. Fragments of the same
+// frame share an id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fragment {
+    pub id: u32,
+    pub index: u16,
+    pub count: u16,
+}
+
+// Version 2 frames are laid out as
+//
+//   magic "hPNG", version, flags, content type, message length (u32)
+//   name length (u8) and UTF-8 name                 if named
+//   fragment id (u32), index and count (u16 each)  if fragmented
+//   nonce, recipient count and wrapped keys         if encrypted
+//   message
+//   critical chunk digest                           if bound to them
+//   image digest                                    if bound to it
+//   SHA-256 of everything above
+//   public key and signature                        if signed
+//
+// Version 1 frames have no content type, fragment or hash, and are still read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub version: u8,
+    pub content_type: ContentType,
+    // compressed before encryption
+    pub compressed: bool,
+    // names let one chunk type hold several messages
+    pub name: Option<String>,
+    pub fragment: Option<Fragment>,
+    pub message: Vec<u8>,
+    pub encryption: Option<Encryption>,
+    pub critical_digest: Option<[u8; 32]>,
+    pub image_digest: Option<[u8; 32]>,
+    pub signature: Option<PayloadSignature>,
+}
+
+impl Payload {
+    pub const MAGIC: [u8; 4] = *b"hPNG";
+    pub const VERSION: u8 = 2;
+    const LEGACY_VERSION: u8 = 1;
+
+    const FLAG_SIGNED: u8 = 0b0000_0001;
+    const FLAG_CRITICAL_DIGEST: u8 = 0b0000_0010;
+    const FLAG_IMAGE_DIGEST: u8 = 0b0000_0100;
+    const FLAG_ENCRYPTED: u8 = 0b0000_1000;
+    const FLAG_COMPRESSED: u8 = 0b0001_0000;
+    const FLAG_FRAGMENTED: u8 = 0b0010_0000;
+    const FLAG_NAMED: u8 = 0b0100_0000;
+    const LEGACY_FLAGS: u8 = 0b0000_1111;
+    const KNOWN_FLAGS: u8 = 0b0111_1111;
+
+    // header and hash of an unsigned, unencrypted frame
+    pub const OVERHEAD: usize = 11 + 32;
+    // and of a fragment frame
+    pub const FRAGMENT_OVERHEAD: usize = Self::OVERHEAD + 8;
+
+    pub fn new(message: Vec<u8>) -> Self {
+        Payload {
+            version: Self::VERSION,
+            content_type: ContentType::Text,
+            compressed: false,
+            name: None,
+            fragment: None,
+            message,
+            encryption: None,
+            critical_digest: None,
+            image_digest: None,
+            signature: None,
+        }
+    }
+
+    pub fn is_payload(data: &[u8]) -> bool {
+        data.starts_with(&Self::MAGIC)
+    }
+
+    // The bytes covered by the signature: everything except the signature
+    // itself, with the signed flag already set.
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        self.encode(true, false)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.encode(self.signature.is_some(), true)
+    }
+
+    fn encode(&self, signed: bool, with_signature: bool) -> Vec<u8> {
+        let legacy = self.version == Self::LEGACY_VERSION;
+        let mut flags = 0;
+        if signed {
+            flags |= Self::FLAG_SIGNED;
+        }
+        if self.critical_digest.is_some() {
+            flags |= Self::FLAG_CRITICAL_DIGEST;
+        }
+        if self.image_digest.is_some() {
+            flags |= Self::FLAG_IMAGE_DIGEST;
+        }
+        if self.encryption.is_some() {
+            flags |= Self::FLAG_ENCRYPTED;
+        }
+        if self.compressed {
+            flags |= Self::FLAG_COMPRESSED;
+        }
+        if self.fragment.is_some() {
+            flags |= Self::FLAG_FRAGMENTED;
+        }
+        if self.name.is_some() {
+            flags |= Self::FLAG_NAMED;
+        }
+
+        let mut bytes = Self::MAGIC.to_vec();
+        bytes.push(self.version);
+        bytes.push(flags);
+        if !legacy {
+            bytes.push(self.content_type.as_byte());
+        }
+        bytes.extend((self.message.len() as u32).to_be_bytes());
+
+        // checked to fit in 255 bytes by `check_name`
+        if let Some(name) = &self.name {
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+        }
+        if let Some(fragment) = &self.fragment {
+            bytes.extend(fragment.id.to_be_bytes());
+            bytes.extend(fragment.index.to_be_bytes());
+            bytes.extend(fragment.count.to_be_bytes());
+        }
+        // when encrypted, `message` holds the ciphertext
+        if let Some(encryption) = &self.encryption {
+            bytes.extend(encryption.nonce);
+            bytes.push(encryption.recipients.len() as u8);
+            for recipient in &encryption.recipients {
+                bytes.extend(recipient.ephemeral_public);
+                bytes.extend(recipient.wrapped_key);
+            }
+        }
+        bytes.extend(&self.message);
+
+        if let Some(digest) = &self.critical_digest {
+            bytes.extend(digest);
+        }
+        if let Some(digest) = &self.image_digest {
+            bytes.extend(digest);
+        }
+        if !legacy {
+            let hash = Sha256::digest(&bytes);
+            bytes.extend(hash);
+        }
+
+        if let (true, Some(signature)) = (with_signature, &self.signature) {
+            bytes.extend(signature.public_key);
+            bytes.extend(signature.signature);
+        }
+
+        bytes
+    }
+
+    // Splits framed bytes into fragment frames carrying at most `size` bytes
+    // each, including their own framing.
+    pub fn fragment(data: &[u8], size: usize) -> Result<Vec<Vec<u8>>, Error> {
+        if size <= Self::FRAGMENT_OVERHEAD {
+            return Err(Error::IncompatibleOptions(
+                "--fragment-size must leave room for the 51 bytes of fragment framing",
+            ));
+        }
+        let pieces: Vec<&[u8]> = data.chunks(size - Self::FRAGMENT_OVERHEAD).collect();
+        let count = u16::try_from(pieces.len()).map_err(|_| {
+            Error::IncompatibleOptions("--fragment-size would need more than 65535 fragments")
+        })?;
+        // random, so the same message stored twice doesn't share an id
+        let id = OsRng.next_u32();
+
+        Ok(pieces
+            .iter()
+            .enumerate()
+            .map(|(index, piece)| {
+                let mut payload = Payload::new(piece.to_vec());
+                payload.content_type = ContentType::Binary;
+                payload.fragment = Some(Fragment {
+                    id,
+                    index: index as u16,
+                    count,
+                });
+                payload.as_bytes()
+            })
+            .collect())
+    }
+
+    // Joins fragment frames back into the data they were split from. Anything
+    // else is passed through, and results keep the position of their first
+    // fragment. Malformed frames and incomplete fragments come back as errors
+    // in their place, so they don't hide the messages around them.
+    pub fn reassemble(data: Vec<Vec<u8>>) -> Vec<Result<Vec<u8>, Error>> {
+        Self::reassemble_indexed(data)
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect()
+    }
+
+    // Like `reassemble`, along with the positions in `data` each result was
+    // joined from.
+    pub fn reassemble_indexed(data: Vec<Vec<u8>>) -> Vec<Joined> {
+        // finished entries, or the id of the fragments an entry waits for
+        let mut entries: Vec<Result<Joined, u32>> = Vec::new();
+        let mut fragments: BTreeMap<u32, Vec<(usize, Payload)>> = BTreeMap::new();
+        for (position, data) in data.into_iter().enumerate() {
+            if !Self::is_payload(&data) {
+                entries.push(Ok((vec![position], Ok(data))));
+                continue;
+            }
+            let payload = match Payload::try_from(data.as_slice()) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    entries.push(Ok((vec![position], Err(err))));
+                    continue;
+                }
+            };
+            match payload.fragment {
+                Some(fragment) => {
+                    if !fragments.contains_key(&fragment.id) {
+                        entries.push(Err(fragment.id));
+                    }
+                    fragments
+                        .entry(fragment.id)
+                        .or_default()
+                        .push((position, payload));
+                }
+                None => entries.push(Ok((vec![position], Ok(data)))),
+            }
+        }
+
+        entries
+            .into_iter()
+            .map(|entry| {
+                let id = match entry {
+                    Ok(joined) => return joined,
+                    Err(id) => id,
+                };
+                let mut pieces = fragments.remove(&id).unwrap();
+                pieces.sort_by_key(|(_, payload)| payload.fragment.unwrap().index);
+                let count = pieces[0].1.fragment.unwrap().count;
+                let complete = pieces.len() == count as usize
+                    && pieces.iter().enumerate().all(|(index, (_, payload))| {
+                        let fragment = payload.fragment.unwrap();
+                        fragment.index as usize == index && fragment.count == count
+                    });
+                let mut positions: Vec<usize> =
+                    pieces.iter().map(|(position, _)| *position).collect();
+                positions.sort();
+                if !complete {
+                    return (
+                        positions,
+                        Err(Error::MalformedPayload("fragments are missing")),
+                    );
+                }
+                let data = pieces
+                    .into_iter()
+                    .flat_map(|(_, payload)| payload.message)
+                    .collect();
+                (positions, Ok(data))
+            })
+            .collect()
+    }
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+// Compressed messages are refused past this size when encoding, so anything
+// inflating further is damaged or a zlib bomb.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    decompress_within(data, MAX_DECOMPRESSED_SIZE)
+}
+
+fn decompress_within(data: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| Error::MalformedPayload("compressed message is damaged"))?;
+    if decompressed.len() > limit {
+        return Err(Error::MalformedPayload(
+            "compressed message inflates past the 64 MiB limit",
+        ));
+    }
+    Ok(decompressed)
+}
+
+impl TryFrom<&[u8]> for Payload {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < 5 || !Self::is_payload(value) {
+            return Err(Error::MalformedPayload("missing payload header"));
+        }
+
+        let version = value[4];
+        let (known_flags, header_size) = match version {
+            Self::LEGACY_VERSION => (Self::LEGACY_FLAGS, 10),
+            Self::VERSION => (Self::KNOWN_FLAGS, 11),
+            _ => return Err(Error::UnsupportedPayloadVersion(version)),
+        };
+        if value.len() < header_size {
+            return Err(Error::MalformedPayload("payload is truncated"));
+        }
+
+        let flags = value[5];
+        if flags & !known_flags != 0 {
+            return Err(Error::MalformedPayload("unknown payload flags"));
+        }
+        let content_type = match version {
+            Self::LEGACY_VERSION => ContentType::Text,
+            _ => ContentType::from_byte(value[6])?,
+        };
+        let length =
+            u32::from_be_bytes(value[header_size - 4..header_size].try_into().unwrap()) as usize;
+        let mut rest = &value[header_size..];
+
+        let name = if flags & Self::FLAG_NAMED != 0 {
+            let length = take(&mut rest, 1)?[0] as usize;
+            let name = String::from_utf8(take(&mut rest, length)?.to_vec())
+                .map_err(|_| Error::MalformedPayload("message name is not UTF-8"))?;
+            Some(name)
+        } else {
+            None
+        };
+
+        let fragment = if flags & Self::FLAG_FRAGMENTED != 0 {
+            let id = u32::from_be_bytes(take(&mut rest, 4)?.try_into().unwrap());
+            let index = u16::from_be_bytes(take(&mut rest, 2)?.try_into().unwrap());
+            let count = u16::from_be_bytes(take(&mut rest, 2)?.try_into().unwrap());
+            if index >= count {
+                return Err(Error::MalformedPayload("fragment index is out of range"));
+            }
+            Some(Fragment { id, index, count })
+        } else {
+            None
+        };
+
+        let encryption = if flags & Self::FLAG_ENCRYPTED != 0 {
+            let nonce = take(&mut rest, 12)?.try_into().unwrap();
+            let count = take(&mut rest, 1)?[0];
+            let recipients = (0..count)
+                .map(|_| {
+                    Ok(WrappedKey {
+                        ephemeral_public: take(&mut rest, 32)?.try_into().unwrap(),
+                        wrapped_key: take(&mut rest, 48)?.try_into().unwrap(),
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Some(Encryption { nonce, recipients })
+        } else {
+            None
+        };
+
+        let message = take(&mut rest, length)?.to_vec();
+
+        let critical_digest = if flags & Self::FLAG_CRITICAL_DIGEST != 0 {
+            Some(take(&mut rest, 32)?.try_into().unwrap())
+        } else {
+            None
+        };
+
+        let image_digest = if flags & Self::FLAG_IMAGE_DIGEST != 0 {
+            Some(take(&mut rest, 32)?.try_into().unwrap())
+        } else {
+            None
+        };
+
+        if version != Self::LEGACY_VERSION {
+            let hashed = value.len() - rest.len();
+            if take(&mut rest, 32)? != Sha256::digest(&value[..hashed]).as_slice() {
+                return Err(Error::CorruptPayload);
+            }
+        }
+
+        let signature = if flags & Self::FLAG_SIGNED != 0 {
+            Some(PayloadSignature {
+                public_key: take(&mut rest, 32)?.try_into().unwrap(),
+                signature: take(&mut rest, 64)?.try_into().unwrap(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Payload {
+            version,
+            content_type,
+            compressed: flags & Self::FLAG_COMPRESSED != 0,
+            name,
+            fragment,
+            message,
+            encryption,
+            critical_digest,
+            image_digest,
+            signature,
+        })
+    }
+}
+
+fn take<'a>(rest: &mut &'a [u8], size: usize) -> Result<&'a [u8], Error> {
+    if rest.len() < size {
+        return Err(Error::MalformedPayload("payload is truncated"));
+    }
+    let (field, remainder) = rest.split_at(size);
+    *rest = remainder;
+    Ok(field)
+}
+
+impl Display for Payload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hidepng payload v{}", self.version)?;
+        if let Some(fragment) = &self.fragment {
+            return write!(
+                f,
+                ", fragment {} of {} ({:08x}), {} bytes",
+                fragment.index + 1,
+                fragment.count,
+                fragment.id,
+                self.message.len()
+            );
+        }
+        let content = match self.content_type {
+            ContentType::Text => "message",
+            ContentType::Binary => "binary message",
+        };
+        write!(f, ", {} byte {}", self.message.len(), content)?;
+        if let Some(name) = &self.name {
+            write!(f, " `{}`", name)?;
+        }
+        if self.compressed {
+            write!(f, ", compressed")?;
+        }
+        if let Some(encryption) = &self.encryption {
+            write!(
+                f,
+                ", encrypted for {} recipients",
+                encryption.recipients.len()
+            )?;
+        }
+        if self.critical_digest.is_some() {
+            write!(f, ", critical chunk digest")?;
+        }
+        if self.image_digest.is_some() {
+            write!(f, ", image digest")?;
+        }
+        if let Some(signature) = &self.signature {
+            write!(f, ", signed by {}", hex::encode(signature.public_key))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = Payload {
+            version: Payload::VERSION,
+            content_type: ContentType::Binary,
+            compressed: true,
+            name: Some("notes".to_string()),
+            fragment: None,
+            message: b"provenance".to_vec(),
+            encryption: Some(Encryption {
+                nonce: [3; 12],
+                recipients: vec![
+                    WrappedKey {
+                        ephemeral_public: [4; 32],
+                        wrapped_key: [5; 48],
+                    };
+                    2
+                ],
+            }),
+            critical_digest: Some([7; 32]),
+            image_digest: Some([8; 32]),
+            signature: Some(PayloadSignature {
+                public_key: [1; 32],
+                signature: [2; 64],
+            }),
+        };
+
+        let bytes = payload.as_bytes();
+        assert!(Payload::is_payload(&bytes));
+        assert_eq!(Payload::try_from(bytes.as_slice()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_signed_bytes_exclude_signature() {
+        let mut payload = Payload::new(b"provenance".to_vec());
+        let unsigned = payload.signed_bytes();
+        payload.signature = Some(PayloadSignature {
+            public_key: [1; 32],
+            signature: [2; 64],
+        });
+        assert_eq!(payload.signed_bytes(), unsigned);
+        assert_eq!(payload.as_bytes().len(), unsigned.len() + 96);
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut bytes = Payload::new(b"provenance".to_vec()).as_bytes();
+        bytes[4] = 9;
+        assert!(matches!(
+            Payload::try_from(bytes.as_slice()),
+            Err(Error::UnsupportedPayloadVersion(9))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        let bytes = Payload::new(b"provenance".to_vec()).as_bytes();
+        assert!(Payload::try_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_version() {
+        let mut bytes = Payload::MAGIC.to_vec();
+        bytes.extend([1, 0, 0, 0, 0, 5]);
+        bytes.extend(b"hello");
+        let payload = Payload::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(payload.version, 1);
+        assert_eq!(payload.message, b"hello");
+        assert_eq!(payload.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_corrupt_payload() {
+        let mut bytes = Payload::new(b"provenance".to_vec()).as_bytes();
+        bytes[12] ^= 1;
+        assert!(matches!(
+            Payload::try_from(bytes.as_slice()),
+            Err(Error::CorruptPayload)
+        ));
+
+        let mut bytes = Payload::new(b"provenance".to_vec()).as_bytes();
+        bytes[5] = 0b1000_0000;
+        assert!(Payload::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_fragments_reassemble() {
+        let mut payload = Payload::new(b"a message split across several chunks".repeat(4));
+        payload.compressed = true;
+        let framed = payload.as_bytes();
+
+        let mut pieces = Payload::fragment(&framed, 80).unwrap();
+        assert_eq!(pieces.len(), 7);
+        assert!(pieces.iter().all(|piece| piece.len() <= 80));
+        pieces.swap(0, 3);
+        pieces.insert(2, b"unframed".to_vec());
+        let joined: Vec<Vec<u8>> = Payload::reassemble(pieces.clone())
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(joined, [framed.clone(), b"unframed".to_vec()]);
+
+        let indexed = Payload::reassemble_indexed(pieces.clone());
+        assert_eq!(indexed[0].0, [0, 1, 3, 4, 5, 6, 7]);
+        assert_eq!(indexed[1].0, [2]);
+
+        // the same message fragmented again gets an id of its own
+        let again = Payload::fragment(&framed, 80).unwrap();
+        let joined = Payload::reassemble([pieces.clone(), again].concat());
+        assert_eq!(joined.len(), 3);
+        assert_eq!(joined[2].as_ref().unwrap(), &framed);
+
+        // a forged frame is reported in its place, the rest still joins
+        let mut forged = Payload::new(b"forged".to_vec()).as_bytes();
+        forged[20] ^= 1;
+        pieces.push(forged);
+        let joined = Payload::reassemble_indexed(pieces.clone());
+        assert_eq!(joined.len(), 3);
+        assert_eq!(joined[0].1.as_ref().unwrap(), &framed);
+        assert_eq!(joined[2].0, [8]);
+        assert!(joined[2].1.is_err());
+
+        pieces.remove(4);
+        let joined = Payload::reassemble_indexed(pieces);
+        assert_eq!(joined[0].0, [0, 1, 3, 4, 5, 6]);
+        assert!(joined[0].1.is_err());
+        assert!(joined[1].1.is_ok());
+        assert!(Payload::fragment(b"data", Payload::FRAGMENT_OVERHEAD).is_err());
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let message = b"compressible ".repeat(20);
+        let compressed = compress(&message);
+        assert!(compressed.len() < message.len());
+        assert_eq!(decompress(&compressed).unwrap(), message);
+        assert!(decompress(b"not zlib").is_err());
+        assert!(decompress_within(&compressed, message.len()).is_ok());
+        assert!(matches!(
+            decompress_within(&compressed, message.len() - 1),
+            Err(Error::MalformedPayload(_))
+        ));
+    }
+}
@@ -1,26 +1,122 @@
 use crate::{
     chunk::Chunk,
+    chunk_type::ChunkType,
     errors::{self, Error},
+    known_chunk::{ImageHeader, KnownChunk, PaletteEntry},
 };
-use anyhow;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
+// Where a new chunk goes relative to the image data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    BeforeImageData,
+    #[default]
+    AfterImageData,
+    BeforeEnd,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum VerifyCrc {
+    // accept every chunk, recomputing bad CRCs
+    Skip,
+    // as skip, but note each recomputed CRC as a fix
+    Warn,
+    #[default]
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub verify_crc: VerifyCrc,
+    // skip unreadable bytes up to the next chunk with a valid CRC, and patch
+    // up the signature and a missing IEND
+    pub resync: bool,
+}
+
+impl ParseOptions {
+    // Refuses anything that is not a well-formed PNG.
+    pub fn strict() -> Self {
+        ParseOptions {
+            verify_crc: VerifyCrc::Error,
+            resync: false,
+        }
+    }
+
+    // What `repair` uses to get at whatever survived.
+    pub fn lenient() -> Self {
+        ParseOptions {
+            verify_crc: VerifyCrc::Warn,
+            resync: true,
+        }
+    }
+}
+
+// Offsets are from the start of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    Signature,
+    Crc {
+        offset: usize,
+        chunk_type: String,
+        stored: u32,
+        computed: u32,
+    },
+    Skipped {
+        offset: usize,
+        length: usize,
+    },
+    MissingEnd,
+}
+
+impl Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fix::Signature => write!(f, "restored the PNG signature"),
+            Fix::Crc {
+                offset,
+                chunk_type,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "recomputed CRC of {} chunk at byte {}: {:08x} -> {:08x}",
+                chunk_type, offset, stored, computed
+            ),
+            Fix::Skipped { offset, length } => {
+                write!(f, "skipped {} unreadable bytes at byte {}", length, offset)
+            }
+            Fix::MissingEnd => write!(f, "added the missing IEND chunk"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Png {
     chunks: Vec<Chunk>,
 }
 
 impl Png {
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
         Png { chunks }
     }
 
+    #[allow(
+        dead_code,
+        reason = "commands insert by placement instead, kept for the tests"
+    )]
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    #[allow(
+        dead_code,
+        reason = "remove drops whole entries instead, kept for the tests"
+    )]
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
         let position = self
             .chunks
@@ -31,18 +127,53 @@ impl Png {
             None => Err(Error::ChunkTypeNotFound(chunk_type.to_string())),
             Some(index) => {
                 let chunk = self.chunks.remove(index);
-                return Ok(chunk);
+                Ok(chunk)
             }
         }
     }
 
-    pub fn insert_between(&mut self, chunk: Chunk) {
+    // Animated PNGs keep frame data in `fdAT` chunks after the `IDAT`s, so the
+    // chunk goes after whichever image data comes last.
+    // Returns the index the chunk was inserted at.
+    pub fn insert_between(&mut self, chunk: Chunk) -> usize {
         let pos = self
             .chunks
             .iter()
-            .rposition(|chunk| chunk.chunk_type().to_string() == "IDAT")
+            .rposition(|chunk| matches!(chunk.chunk_type().to_string().as_str(), "IDAT" | "fdAT"))
             .unwrap_or(1);
         self.chunks.insert(pos + 1, chunk);
+        pos + 1
+    }
+
+    // Returns the index the chunk was inserted at.
+    pub fn insert_at(&mut self, chunk: Chunk, placement: Placement) -> usize {
+        let pos = match placement {
+            Placement::AfterImageData => return self.insert_between(chunk),
+            // the default image's fcTL belongs with its IDAT
+            Placement::BeforeImageData => self.chunks.iter().position(|chunk| {
+                matches!(chunk.chunk_type().to_string().as_str(), "IDAT" | "fcTL")
+            }),
+            Placement::BeforeEnd => self
+                .chunks
+                .iter()
+                .rposition(|chunk| chunk.chunk_type().bytes() == *b"IEND"),
+        }
+        .unwrap_or(self.chunks.len());
+        self.chunks.insert(pos, chunk);
+        pos
+    }
+
+    // Swaps every IDAT for `idats`, placed where the first IDAT was.
+    pub fn replace_image_data(&mut self, idats: Vec<Chunk>) -> Result<(), Error> {
+        let is_idat = |chunk: &Chunk| chunk.chunk_type().bytes() == *b"IDAT";
+        let pos = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .ok_or(Error::ChunkTypeNotFound("IDAT".to_string()))?;
+        self.chunks.retain(|chunk| !is_idat(chunk));
+        self.chunks.splice(pos..pos, idats);
+        Ok(())
     }
 
     pub fn header(&self) -> &[u8; 8] {
@@ -53,6 +184,10 @@ impl Png {
         &self.chunks
     }
 
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        &mut self.chunks
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<Vec<&Chunk>> {
         let chunks: Vec<&Chunk> = self
             .chunks
@@ -67,39 +202,169 @@ impl Png {
         Some(chunks)
     }
 
+    pub fn image_header(&self) -> Result<ImageHeader, Error> {
+        let ihdr = self
+            .chunks
+            .first()
+            .filter(|chunk| chunk.chunk_type().to_string() == "IHDR")
+            .ok_or(Error::InvalidFile("First chunk is not IHDR"))?;
+
+        match ihdr.parse_known()? {
+            KnownChunk::Ihdr(header) => Ok(header),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn palette(&self) -> Result<Option<Vec<PaletteEntry>>, Error> {
+        let Some(plte) = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().bytes() == *b"PLTE")
+        else {
+            return Ok(None);
+        };
+
+        match plte.parse_known()? {
+            KnownChunk::Plte(entries) => Ok(Some(entries)),
+            _ => unreachable!(),
+        }
+    }
+
+    // Replaces the first chunk of the same type, or inserts it like
+    // `insert_between` when there is none.
+    pub fn replace_chunk(&mut self, chunk: Chunk) {
+        match self
+            .chunks
+            .iter()
+            .position(|existing| existing.chunk_type() == chunk.chunk_type())
+        {
+            Some(pos) => self.chunks[pos] = chunk,
+            None => {
+                self.insert_between(chunk);
+            }
+        }
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         self.header()
             .iter()
             .copied()
-            .chain(self.chunks.iter().map(|chunk| chunk.as_bytes()).flatten())
+            .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
             .collect()
     }
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = anyhow::Error;
+impl Png {
+    // Parses `value`, fixing what `options` allows instead of failing.
+    pub fn parse(value: &[u8], options: ParseOptions) -> Result<(Png, Vec<Fix>), Error> {
+        if value.len() < Self::STANDARD_HEADER.len() {
+            return Err(Error::InvalidFile("File size too small"));
+        }
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < 20 {
-            return Err(errors::Error::InvalidFile("File size too small").into());
+        let mut fixes = Vec::new();
+        if value[..Self::STANDARD_HEADER.len()] != Self::STANDARD_HEADER {
+            if !options.resync {
+                return Err(Error::InvalidPngSignature);
+            }
+            fixes.push(Fix::Signature);
         }
 
-        let (png_signature, value) = value.split_at(Self::STANDARD_HEADER.len());
+        let mut chunks: Vec<Chunk> = Vec::new();
+        let mut offset = Self::STANDARD_HEADER.len();
+        while offset < value.len() {
+            let ended = chunks
+                .last()
+                .is_some_and(|chunk| chunk.chunk_type().bytes() == *b"IEND");
+            // nothing belongs after IEND
+            let raw = if ended {
+                None
+            } else {
+                read_chunk(&value[offset..])
+            };
+
+            let Some((chunk_type, data, stored)) = raw else {
+                if !options.resync {
+                    return Err(Error::InvalidFile("Chunk is cut short or unreadable"));
+                }
+                // resync on the next chunk that checks out, or give up on the rest
+                let next = (offset + 1..value.len())
+                    .filter(|_| !ended)
+                    .find(|&next| {
+                        read_chunk(&value[next..]).is_some_and(|(chunk_type, data, stored)| {
+                            Chunk::calculate_crc(&chunk_type, data) == stored
+                        })
+                    })
+                    .unwrap_or(value.len());
+                fixes.push(Fix::Skipped {
+                    offset,
+                    length: next - offset,
+                });
+                offset = next;
+                continue;
+            };
+
+            let computed = Chunk::calculate_crc(&chunk_type, data);
+            if computed != stored {
+                match options.verify_crc {
+                    VerifyCrc::Error => return Err(Error::CrcMismatch),
+                    VerifyCrc::Warn => fixes.push(Fix::Crc {
+                        offset,
+                        chunk_type: chunk_type.to_string(),
+                        stored,
+                        computed,
+                    }),
+                    VerifyCrc::Skip => {}
+                }
+            }
+            offset += data.len() + Chunk::CHUNK_METADATA_SIZE as usize;
+            chunks.push(Chunk::new(chunk_type, data.to_vec()));
+        }
 
-        if png_signature != Self::STANDARD_HEADER {
-            return Err(Error::InvalidPngSignature.into());
+        let ended = chunks
+            .last()
+            .is_some_and(|chunk| chunk.chunk_type().bytes() == *b"IEND");
+        if options.resync && !ended {
+            chunks.push(Chunk::new(
+                ChunkType::try_from(*b"IEND").unwrap(),
+                Vec::new(),
+            ));
+            fixes.push(Fix::MissingEnd);
         }
 
-        let mut index = 0;
-        let mut chunks = Vec::new();
+        Ok((Png { chunks }, fixes))
+    }
+}
+
+// The type, data and stored CRC of the chunk `bytes` starts with, if its
+// header is plausible and it fits. The checks are cheap, as resyncing runs
+// them at every offset before paying for a CRC.
+fn read_chunk(bytes: &[u8]) -> Option<(ChunkType, &[u8], u32)> {
+    let header = bytes.get(..8)?;
+    let length = u32::from_be_bytes(header[..4].try_into().unwrap());
+    if length > i32::MAX as u32 || !header[4..].iter().all(u8::is_ascii_alphabetic) {
+        return None;
+    }
+    let length = length as usize;
+    let crc = bytes.get(8 + length..12 + length)?;
+    let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&header[4..]).unwrap()).ok()?;
+    let data = &bytes[8..8 + length];
+    Some((
+        chunk_type,
+        data,
+        u32::from_be_bytes(crc.try_into().unwrap()),
+    ))
+}
 
-        while index < value.len() {
-            let chunk = Chunk::try_from(value.get(index..).unwrap())?;
-            index += (chunk.length() + Chunk::CHUNK_METADATA_SIZE) as usize;
-            chunks.push(chunk);
+impl TryFrom<&[u8]> for Png {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < 20 {
+            return Err(errors::Error::InvalidFile("File size too small").into());
         }
 
-        Ok(Png { chunks })
+        let (png, _) = Png::parse(value, ParseOptions::strict())?;
+        Ok(png)
     }
 }
 
@@ -123,6 +388,10 @@ mod tests {
         let _png = Png::try_from(PNG_FILE.as_slice()).unwrap();
     }
 
+    #[allow(
+        clippy::vec_init_then_push,
+        reason = "one push per chunk reads like the fixture"
+    )]
     fn testing_chunks() -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -246,6 +515,19 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_palette() {
+        let contents = std::fs::read("assets/catgurl.png").unwrap();
+        let png = Png::try_from(contents.as_slice()).unwrap();
+        let palette = png.palette().unwrap().unwrap();
+        assert_eq!(
+            palette.len() * 3,
+            png.chunk_by_type("PLTE").unwrap()[0].data().len()
+        );
+
+        assert!(testing_png().palette().unwrap().is_none());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -260,6 +542,122 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_parse_intact_file() {
+        let (png, fixes) = Png::parse(&PNG_FILE, ParseOptions::strict()).unwrap();
+        assert!(fixes.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_parse_bad_crc() {
+        // last byte of the RuSt chunk's CRC, just before IEND
+        let mut bytes = PNG_FILE.to_vec();
+        let crc_byte = bytes.len() - 13;
+        bytes[crc_byte] ^= 0xff;
+
+        assert!(matches!(
+            Png::parse(&bytes, ParseOptions::strict()),
+            Err(Error::CrcMismatch)
+        ));
+
+        let parse = |verify_crc| {
+            let options = ParseOptions {
+                verify_crc,
+                resync: false,
+            };
+            Png::parse(&bytes, options).unwrap()
+        };
+        let (png, fixes) = parse(VerifyCrc::Warn);
+        assert!(matches!(&fixes[..], [Fix::Crc { chunk_type, .. }] if chunk_type == "RuSt"));
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        assert!(parse(VerifyCrc::Skip).1.is_empty());
+    }
+
+    #[test]
+    fn test_parse_resyncs_after_garbage() {
+        let rust_chunk = PNG_FILE.len() - 12 - 15;
+        let mut bytes = PNG_FILE[..rust_chunk].to_vec();
+        bytes.extend(b"junk");
+        bytes.extend(&PNG_FILE[rust_chunk..]);
+        bytes.extend(b"trailer");
+
+        assert!(Png::parse(&bytes, ParseOptions::strict()).is_err());
+        let (png, fixes) = Png::parse(&bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(
+            fixes,
+            [
+                Fix::Skipped {
+                    offset: rust_chunk,
+                    length: 4
+                },
+                Fix::Skipped {
+                    offset: bytes.len() - 7,
+                    length: 7
+                },
+            ]
+        );
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_parse_resyncs_past_implausible_headers() {
+        let rust_chunk = PNG_FILE.len() - 12 - 15;
+        let mut junk = vec![0xff; 64];
+        junk.extend(u32::MAX.to_be_bytes());
+        junk.extend(b"RuSt");
+        let mut bytes = PNG_FILE[..rust_chunk].to_vec();
+        bytes.extend(&junk);
+        bytes.extend(&PNG_FILE[rust_chunk..]);
+
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+        let (png, fixes) = Png::parse(&bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(
+            fixes,
+            [Fix::Skipped {
+                offset: rust_chunk,
+                length: junk.len()
+            }]
+        );
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_parse_restores_signature_and_end() {
+        let mut bytes = PNG_FILE[..PNG_FILE.len() - 12].to_vec();
+        bytes[1] = b'X';
+
+        let (png, fixes) = Png::parse(&bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(fixes, [Fix::Signature, Fix::MissingEnd]);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_insert_at() {
+        let types = |png: &Png| -> Vec<String> {
+            png.chunks()
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect()
+        };
+        let mut png = Png::try_from(PNG_FILE.as_slice()).unwrap();
+        let first_idat = types(&png).iter().position(|t| t == "IDAT").unwrap();
+
+        let index = png.insert_at(
+            chunk_from_strings("ruSt", "before").unwrap(),
+            Placement::BeforeImageData,
+        );
+        assert_eq!(index, first_idat);
+        assert_eq!(types(&png)[index + 1], "IDAT");
+
+        let index = png.insert_at(
+            chunk_from_strings("ruSt", "end").unwrap(),
+            Placement::BeforeEnd,
+        );
+        assert_eq!(index, png.chunks().len() - 2);
+        assert_eq!(types(&png).last().unwrap(), "IEND");
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    chunk_type::ChunkType,
+    errors::Error,
+    image::RawImage,
+    keys,
+    payload::{Payload, PayloadSignature},
+    png::Png,
+};
+
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+pub fn read_signing_key(path: &Path) -> anyhow::Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&keys::read_key_bytes(path)?))
+}
+
+pub fn read_verifying_key(path: &Path) -> anyhow::Result<VerifyingKey> {
+    Ok(VerifyingKey::from_bytes(&keys::read_key_bytes(path)?)
+        .map_err(|_| Error::InvalidKey(path.display().to_string()))?)
+}
+
+// Digest of every critical chunk except the ones carrying payloads, so any
+// change to the image data invalidates a bound signature.
+pub fn critical_chunks_digest(png: &Png, payload_type: &ChunkType) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for chunk in png.chunks() {
+        if chunk.chunk_type().is_critical() && chunk.chunk_type() != payload_type {
+            hasher.update(chunk.as_bytes());
+        }
+    }
+    hasher.finalize().into()
+}
+
+// Digest of what the image looks like rather than how it is stored: the IHDR
+// fields describing the pixels, the palette and the unfiltered pixel data.
+// Recompressing the image or editing ancillary chunks leaves it unchanged.
+pub fn image_digest(png: &Png) -> Result<[u8; 32], Error> {
+    let image = RawImage::from_png(png)?;
+    let header = &image.header;
+
+    let mut hasher = Sha256::new();
+    hasher.update(header.width.to_be_bytes());
+    hasher.update(header.height.to_be_bytes());
+    hasher.update([header.bit_depth, header.color_type.value()]);
+    if let Some(palette) = png.chunk_by_type("PLTE") {
+        hasher.update(palette[0].data());
+    }
+    hasher.update(&image.data);
+    Ok(hasher.finalize().into())
+}
+
+pub fn sign(payload: &mut Payload, key: &SigningKey) {
+    let signature = key.sign(&payload.signed_bytes());
+    payload.signature = Some(PayloadSignature {
+        public_key: key.verifying_key().to_bytes(),
+        signature: signature.to_bytes(),
+    });
+}
+
+pub fn verify(
+    payload: &Payload,
+    trusted_key: Option<&VerifyingKey>,
+    critical_digest: Option<[u8; 32]>,
+) -> Result<VerifyingKey, Error> {
+    let signature = payload.signature.as_ref().ok_or(Error::UnsignedPayload)?;
+
+    let signer =
+        VerifyingKey::from_bytes(&signature.public_key).map_err(|_| Error::InvalidSignature)?;
+    if let Some(trusted_key) = trusted_key {
+        if trusted_key != &signer {
+            return Err(Error::UntrustedSigner(hex::encode(signer.to_bytes())));
+        }
+    }
+
+    signer
+        .verify(
+            &payload.signed_bytes(),
+            &Signature::from_bytes(&signature.signature),
+        )
+        .map_err(|_| Error::InvalidSignature)?;
+
+    if let (Some(expected), Some(actual)) = (payload.critical_digest, critical_digest) {
+        if expected != actual {
+            return Err(Error::CriticalChunksModified);
+        }
+    }
+
+    Ok(signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = generate_signing_key();
+        let mut payload = Payload::new(b"provenance".to_vec());
+        sign(&mut payload, &key);
+
+        let signer = verify(&payload, Some(&key.verifying_key()), None).unwrap();
+        assert_eq!(signer, key.verifying_key());
+    }
+
+    #[test]
+    fn test_tampered_message() {
+        let key = generate_signing_key();
+        let mut payload = Payload::new(b"provenance".to_vec());
+        sign(&mut payload, &key);
+        payload.message = b"forgery".to_vec();
+
+        assert!(matches!(
+            verify(&payload, None, None),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_untrusted_signer() {
+        let mut payload = Payload::new(b"provenance".to_vec());
+        sign(&mut payload, &generate_signing_key());
+
+        let other = generate_signing_key().verifying_key();
+        assert!(matches!(
+            verify(&payload, Some(&other), None),
+            Err(Error::UntrustedSigner(_))
+        ));
+    }
+
+    #[test]
+    fn test_unsigned_payload() {
+        let payload = Payload::new(b"provenance".to_vec());
+        assert!(matches!(
+            verify(&payload, None, None),
+            Err(Error::UnsignedPayload)
+        ));
+    }
+
+    #[test]
+    fn test_image_digest_ignores_compression() {
+        use crate::chunk::Chunk;
+        use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+        use std::io::{Read, Write};
+
+        let contents = fs::read("assets/catgurl.png").unwrap();
+        let png = Png::try_from(contents.as_slice()).unwrap();
+
+        let compressed: Vec<u8> = png
+            .chunk_by_type("IDAT")
+            .unwrap()
+            .iter()
+            .flat_map(|chunk| chunk.data().to_vec())
+            .collect();
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut filtered)
+            .unwrap();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&filtered).unwrap();
+
+        let mut chunks: Vec<Chunk> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() != "IDAT")
+            .cloned()
+            .collect();
+        let idat = Chunk::new(
+            ChunkType::try_from(*b"IDAT").unwrap(),
+            encoder.finish().unwrap(),
+        );
+        chunks.insert(chunks.len() - 1, idat);
+        let recompressed = Png::from_chunks(chunks);
+
+        assert_ne!(recompressed.as_bytes(), png.as_bytes());
+        assert_eq!(
+            image_digest(&recompressed).unwrap(),
+            image_digest(&png).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_image_digest_detects_palette_change() {
+        use crate::chunk::Chunk;
+
+        let contents = fs::read("assets/catgurl.png").unwrap();
+        let png = Png::try_from(contents.as_slice()).unwrap();
+
+        let chunks: Vec<Chunk> = png
+            .chunks()
+            .iter()
+            .map(|chunk| {
+                if chunk.chunk_type().to_string() != "PLTE" {
+                    return chunk.clone();
+                }
+                let mut palette = chunk.data().to_vec();
+                palette[0] ^= 1;
+                Chunk::new(chunk.chunk_type().clone(), palette)
+            })
+            .collect();
+
+        assert_ne!(
+            image_digest(&Png::from_chunks(chunks)).unwrap(),
+            image_digest(&png).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_critical_digest_mismatch() {
+        let key = generate_signing_key();
+        let mut payload = Payload::new(b"provenance".to_vec());
+        payload.critical_digest = Some([1; 32]);
+        sign(&mut payload, &key);
+
+        assert!(verify(&payload, None, Some([1; 32])).is_ok());
+        assert!(matches!(
+            verify(&payload, None, Some([2; 32])),
+            Err(Error::CriticalChunksModified)
+        ));
+    }
+}
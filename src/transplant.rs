@@ -0,0 +1,257 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, errors::Error, png::Png};
+
+// Chunks the PNG spec requires before PLTE, and between PLTE and IDAT.
+const BEFORE_PALETTE: [&str; 8] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv", "cLLi",
+];
+const BEFORE_IMAGE_DATA: [&str; 9] = [
+    "bKGD", "hIST", "tRNS", "pHYs", "sPLT", "eXIf", "oFFs", "pCAL", "sCAL",
+];
+// Chunks that may appear only once, so a copy replaces the target's own.
+const SINGLE: [&str; 17] = [
+    "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv", "cLLi", "bKGD", "hIST", "tRNS", "pHYs",
+    "eXIf", "oFFs", "pCAL", "sCAL", "tIME",
+];
+const ANIMATION: [&str; 3] = ["acTL", "fcTL", "fdAT"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    BeforePalette,
+    BeforeImageData,
+    AfterImageData,
+}
+
+impl Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::BeforePalette => write!(f, "before PLTE"),
+            Segment::BeforeImageData => write!(f, "before IDAT"),
+            Segment::AfterImageData => write!(f, "after IDAT"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Copied {
+        chunk_type: String,
+        length: u32,
+        segment: Segment,
+    },
+    Replaced {
+        chunk_type: String,
+        length: u32,
+    },
+    Skipped {
+        chunk_type: String,
+        reason: &'static str,
+    },
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Copied {
+                chunk_type,
+                length,
+                segment,
+            } => write!(f, "copied {} ({} bytes) {}", chunk_type, length, segment),
+            Outcome::Replaced { chunk_type, length } => {
+                write!(f, "replaced {} ({} bytes)", chunk_type, length)
+            }
+            Outcome::Skipped { chunk_type, reason } => {
+                write!(f, "skipped {}: {}", chunk_type, reason)
+            }
+        }
+    }
+}
+
+// Copies the ancillary chunks of `source`, or only those of `types`, into
+// `target`. Chunks not marked safe to copy describe the source's image data
+// and are skipped unless `force` is set.
+pub fn transplant(
+    source: &Png,
+    target: &mut Png,
+    types: &[ChunkType],
+    force: bool,
+) -> Result<Vec<Outcome>, Error> {
+    if let Some(missing) = types.iter().find(|chunk_type| {
+        !source
+            .chunks()
+            .iter()
+            .any(|c| c.chunk_type() == *chunk_type)
+    }) {
+        return Err(Error::ChunkTypeNotFound(missing.to_string()));
+    }
+
+    let mut chunks = target.chunks().to_vec();
+    let mut outcomes = Vec::new();
+    let mut segment = Segment::BeforePalette;
+    for chunk in source.chunks() {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        match name.as_str() {
+            "PLTE" => segment = Segment::BeforeImageData,
+            "IDAT" => segment = Segment::AfterImageData,
+            _ => {}
+        }
+        if !types.is_empty() && !types.contains(chunk_type) {
+            continue;
+        }
+
+        let skip = |reason| Outcome::Skipped {
+            chunk_type: name.clone(),
+            reason,
+        };
+        if chunk_type.is_critical() {
+            if !types.is_empty() {
+                outcomes.push(skip("critical chunks are never copied"));
+            }
+            continue;
+        }
+        if ANIMATION.contains(&name.as_str()) {
+            outcomes.push(skip("animation chunks belong to the source's frames"));
+            continue;
+        }
+        if !chunk_type.is_safe_to_copy() && !force {
+            outcomes.push(skip(
+                "not safe to copy once the image data changed, --force copies it anyway",
+            ));
+            continue;
+        }
+        if chunks
+            .iter()
+            .any(|c| c.chunk_type() == chunk_type && c.data() == chunk.data())
+        {
+            outcomes.push(skip("already in the target"));
+            continue;
+        }
+
+        if SINGLE.contains(&name.as_str()) {
+            if let Some(existing) = chunks.iter_mut().find(|c| c.chunk_type() == chunk_type) {
+                *existing = chunk.clone();
+                outcomes.push(Outcome::Replaced {
+                    chunk_type: name,
+                    length: chunk.length(),
+                });
+                continue;
+            }
+        }
+
+        // known chunks go where the spec wants them, others keep their place
+        // relative to the critical chunks
+        let segment = if BEFORE_PALETTE.contains(&name.as_str()) {
+            Segment::BeforePalette
+        } else if BEFORE_IMAGE_DATA.contains(&name.as_str()) {
+            Segment::BeforeImageData
+        } else {
+            segment
+        };
+        chunks.insert(position(&chunks, segment), chunk.clone());
+        outcomes.push(Outcome::Copied {
+            chunk_type: name,
+            length: chunk.length(),
+            segment,
+        });
+    }
+
+    *target = Png::from_chunks(chunks);
+    Ok(outcomes)
+}
+
+// Index at the end of `segment`.
+fn position(chunks: &[Chunk], segment: Segment) -> usize {
+    let first = |types: &[&str]| {
+        chunks
+            .iter()
+            .position(|chunk| types.contains(&chunk.chunk_type().to_string().as_str()))
+    };
+    match segment {
+        Segment::BeforePalette => first(&["PLTE", "IDAT", "fcTL"]),
+        Segment::BeforeImageData => first(&["IDAT", "fcTL"]),
+        Segment::AfterImageData => first(&["IEND"]),
+    }
+    .unwrap_or(chunks.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png(path: &str) -> Png {
+        let contents = std::fs::read(path).unwrap();
+        Png::try_from(contents.as_slice()).unwrap()
+    }
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_transplant_orders_and_skips() {
+        let mut source = testing_png("assets/catgurl.png");
+        let mut chunks = source.chunks().to_vec();
+        chunks.insert(1, chunk("gAMA", &[0, 0, 177, 143]));
+        chunks.insert(2, chunk("prVt", b"before the palette"));
+        chunks.insert(4, chunk("pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1]));
+        let end = chunks.len() - 1;
+        chunks.insert(end, chunk("tEXt", b"Comment\0hello"));
+        source = Png::from_chunks(chunks);
+
+        let mut target = testing_png("assets/catgurl.png");
+        let outcomes = transplant(&source, &mut target, &[], false).unwrap();
+        assert_eq!(
+            outcomes.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            [
+                "skipped gAMA: not safe to copy once the image data changed, --force copies it anyway",
+                "copied prVt (18 bytes) before PLTE",
+                "copied pHYs (9 bytes) before IDAT",
+                "copied tEXt (13 bytes) after IDAT",
+            ]
+        );
+        assert_eq!(
+            types(&target),
+            ["IHDR", "prVt", "PLTE", "pHYs", "IDAT", "IDAT", "IDAT", "IDAT", "tEXt", "IEND"]
+        );
+
+        // a second run has nothing left to copy, and forcing replaces
+        let gama = ChunkType::from_str("gAMA").unwrap();
+        let phys = ChunkType::from_str("pHYs").unwrap();
+        let outcomes = transplant(&source, &mut target, &[gama, phys], true).unwrap();
+        assert_eq!(
+            outcomes.iter().map(|o| o.to_string()).collect::<Vec<_>>(),
+            [
+                "copied gAMA (4 bytes) before PLTE",
+                "skipped pHYs: already in the target"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transplant_replaces_single_chunks() {
+        let mut source = testing_png("assets/catgurl.png");
+        source.insert_between(chunk("tIME", &[7, 234, 10, 19, 12, 0, 0]));
+        let mut target = testing_png("assets/catgurl.png");
+        target.insert_between(chunk("tIME", &[7, 232, 1, 1, 0, 0, 0]));
+
+        let outcomes = transplant(&source, &mut target, &[], true).unwrap();
+        assert_eq!(outcomes[0].to_string(), "replaced tIME (7 bytes)");
+        assert_eq!(
+            target.chunk_by_type("tIME").unwrap()[0].data(),
+            [7, 234, 10, 19, 12, 0, 0]
+        );
+
+        let missing = ChunkType::from_str("iCCP").unwrap();
+        assert!(transplant(&source, &mut target, &[missing], true).is_err());
+    }
+}
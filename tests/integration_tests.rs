@@ -136,3 +136,1581 @@ fn test_encode_decode_mutiple_chunk_default_chunk_type() {
             .and(predicates::str::contains("secret message 1")),
     );
 }
+
+#[test]
+fn test_inspect_known_chunks() {
+    let args = ["inspect", "-f", "./assets/ryanGosling.png"];
+    let output = create_command(&args);
+
+    output.success().stdout(
+        predicates::str::contains("640x793, 8-bit RGB")
+            .and(predicates::str::contains("(144x144 DPI)"))
+            .and(predicates::str::contains("2023-05-16 23:57:24 UTC"))
+            .and(predicates::str::contains("icc:description: Display")),
+    );
+}
+
+// A 2x2 animated PNG built from a manifest, with a 1/100 s delay on every
+// frame so each one holds 9 bits of frame timing.
+fn animated_png(dir: &std::path::Path, frames: u32) -> String {
+    // a stored zlib block of two all-zero RGBA rows
+    let image_data = format!("7801011200edff{}00120001", "00".repeat(18));
+    let fctl = |sequence: u32| {
+        format!(
+            "{:08x}00000002000000020000000000000000000100640000",
+            sequence
+        )
+    };
+    let mut chunks = vec![
+        r#"{"type": "IHDR", "hex": "00000002000000020806000000"}"#.to_string(),
+        format!(r#"{{"type": "acTL", "hex": "{:08x}00000000"}}"#, frames),
+        format!(r#"{{"type": "fcTL", "hex": "{}"}}"#, fctl(0)),
+        format!(r#"{{"type": "IDAT", "hex": "{}"}}"#, image_data),
+    ];
+    for frame in 1..frames {
+        chunks.push(format!(
+            r#"{{"type": "fcTL", "hex": "{}"}}"#,
+            fctl(frame * 2 - 1)
+        ));
+        chunks.push(format!(
+            r#"{{"type": "fdAT", "hex": "{:08x}{}"}}"#,
+            frame * 2,
+            image_data
+        ));
+    }
+    chunks.push(r#"{"type": "IEND", "text": ""}"#.to_string());
+
+    let manifest = dir.join("animation.json");
+    std::fs::write(
+        &manifest,
+        format!(
+            r#"{{"manifest_version": 1, "chunks": [{}]}}"#,
+            chunks.join(", ")
+        ),
+    )
+    .unwrap();
+    let output_file = dir.join("animation.png");
+    let output_file = output_file.to_str().unwrap();
+    let args = [
+        "manifest",
+        "build",
+        "-f",
+        manifest.to_str().unwrap(),
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    output_file.to_string()
+}
+
+#[test]
+fn test_encode_decode_frame_timing() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    // a framed message needs more frames than the spinner has
+    let args = [
+        "encode",
+        "-f",
+        "./assets/spinner.png",
+        "--method",
+        "frame-timing",
+        "-m",
+        "apng secret",
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .code(8)
+        .stderr(predicates::str::contains(
+            "Message needs 54 bytes but only 14 bytes fit",
+        ));
+
+    let dir = tempfile::tempdir().unwrap();
+    let animation = animated_png(dir.path(), 50);
+    let args = [
+        "encode",
+        "-f",
+        &animation,
+        "--method",
+        "frame-timing",
+        "-m",
+        "apng secret",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = ["decode", "-f", output_file, "--method", "frame-timing"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("apng secret"));
+
+    let args = ["inspect", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("sequence numbers valid"));
+}
+
+#[test]
+fn test_frame_timing_requires_animation() {
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "--method",
+        "frame-timing",
+        "-m",
+        "secret message 1",
+    ];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("not an animated PNG"));
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let key_dir = tempfile::tempdir().unwrap();
+    let key = key_dir.path().join("signing.key");
+    let key = key.to_str().unwrap();
+    let public_key = format!("{}.pub", key);
+    let other_key = key_dir.path().join("other.key");
+    let other_key = other_key.to_str().unwrap();
+    let other_public_key = format!("{}.pub", other_key);
+
+    create_command(&["keygen", "-o", key]).success();
+    create_command(&["keygen", "-o", other_key]).success();
+    create_command(&["keygen", "-o", key]).code(1);
+
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "signed message",
+        "--sign-key",
+        key,
+        "--bind-critical-chunks",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = ["decode", "-f", output_file, "--verify-key", &public_key];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("signed message"));
+
+    let args = ["verify", "-f", output_file, "--verify-key", &public_key];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("critical chunks unchanged"));
+
+    let args = [
+        "verify",
+        "-f",
+        output_file,
+        "--verify-key",
+        &other_public_key,
+    ];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("untrusted key"));
+}
+
+#[test]
+fn test_verify_unsigned_message() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "secret message 1",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = ["verify", "-f", output_file];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("Payload is not signed."));
+}
+
+#[test]
+fn test_bind_image_survives_metadata_edits() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "bound message",
+        "--bind-image",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "encode",
+        "-f",
+        output_file,
+        "-c",
+        "TXTI",
+        "-m",
+        "unrelated metadata",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = ["verify", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("image content unchanged"));
+}
+
+#[test]
+fn test_encrypt_for_recipients() {
+    let key_dir = tempfile::tempdir().unwrap();
+    let keys: Vec<String> = ["alice.key", "bob.key", "eve.key"]
+        .iter()
+        .map(|name| key_dir.path().join(name).to_str().unwrap().to_string())
+        .collect();
+    for key in &keys {
+        create_command(&["keygen", "--kind", "encryption", "-o", key]).success();
+    }
+
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "for alice and bob",
+        "--recipient",
+        &format!("{}.pub", keys[0]),
+        "--recipient",
+        &format!("{}.pub", keys[1]),
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    for key in &keys[..2] {
+        let args = ["decode", "-f", output_file, "--identity", key];
+        create_command(&args)
+            .success()
+            .stdout(predicates::str::contains("for alice and bob"));
+    }
+
+    let args = ["decode", "-f", output_file, "--identity", &keys[2]];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("Decryption failed"));
+
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("Payload is encrypted"));
+}
+
+#[test]
+fn test_decoy_passphrases() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "meet at the docks",
+        "--passphrase",
+        "real",
+        "--decoy-message",
+        "buy milk",
+        "--decoy-passphrase",
+        "decoy",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    for (passphrase, message) in [("real", "meet at the docks"), ("decoy", "buy milk")] {
+        let args = ["decode", "-f", output_file, "--passphrase", passphrase];
+        create_command(&args)
+            .success()
+            .stdout(predicates::str::contains(message));
+    }
+
+    let args = ["decode", "-f", output_file, "--passphrase", "guess"];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("Decryption failed"));
+}
+
+#[test]
+fn test_decoy_message_needs_passphrase() {
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "meet at the docks",
+        "--passphrase",
+        "real",
+        "--decoy-message",
+        "buy milk",
+    ];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("--decoy-passphrase"));
+}
+
+#[test]
+fn test_encode_decode_lsb_with_key() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "in the pixels",
+        "--method",
+        "lsb",
+        "--key",
+        "hunter2",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "decode",
+        "-f",
+        output_file,
+        "--method",
+        "lsb",
+        "--key",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("in the pixels"));
+
+    let args = ["decode", "-f", output_file, "--method", "lsb"];
+    create_command(&args).stdout(predicates::str::contains("in the pixels").not());
+}
+
+#[test]
+fn test_lsb_rejects_image_binding() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "in the pixels",
+        "--method",
+        "lsb",
+        "--bind-image",
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains(
+            "can't be used with pixel methods",
+        ));
+}
+
+#[test]
+fn test_matrix_embedding_and_dry_run() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+    let encode = |extra: &[&str]| {
+        let mut args = vec![
+            "encode",
+            "-f",
+            "./assets/ryanGosling.png",
+            "-m",
+            "fewer changes",
+            "--method",
+            "lsb",
+            "--strategy",
+            "matrix",
+            "-o",
+            output_file,
+        ];
+        args.extend(extra);
+        create_command(&args)
+    };
+
+    encode(&["--dry-run"])
+        .success()
+        .stdout(predicates::str::contains("dry run, nothing written"))
+        .stdout(predicates::str::contains("strategy matrix"));
+    assert_eq!(std::fs::metadata(output_file).unwrap().len(), 0);
+
+    encode(&[]).success();
+    let args = ["decode", "-f", output_file, "--method", "lsb"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("fewer changes"));
+}
+
+#[test]
+fn test_encode_decode_palette() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "between the colours",
+        "--method",
+        "palette",
+        "--key",
+        "hunter2",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "decode",
+        "-f",
+        output_file,
+        "--method",
+        "palette",
+        "--key",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("between the colours"));
+
+    let args = ["decode", "-f", output_file, "--method", "lsb"];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("use --method palette"));
+}
+
+#[test]
+fn test_adaptive_embedding() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "in the busy bits",
+        "--method",
+        "lsb",
+        "--adaptive",
+        "--key",
+        "hunter2",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "decode",
+        "-f",
+        output_file,
+        "--method",
+        "lsb",
+        "--key",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("in the busy bits"));
+}
+
+#[test]
+fn test_decode_detects_lsb_options() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "two bits of blue",
+        "--method",
+        "lsb",
+        "--channels",
+        "b",
+        "--bits-per-sample",
+        "2",
+        "--key",
+        "hunter2",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "decode",
+        "-f",
+        output_file,
+        "--method",
+        "lsb",
+        "--key",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("two bits of blue"));
+}
+
+#[test]
+fn test_transparent_pixels_need_alpha() {
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "nowhere to hide",
+        "--method",
+        "lsb",
+        "--transparent-pixels",
+        "skip",
+        "--dry-run",
+    ];
+    create_command(&args).failure();
+}
+
+#[test]
+fn test_capacity() {
+    let args = ["capacity", "-f", "./assets/spinner.png"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("overhead: 43 bytes"))
+        .stdout(predicates::str::contains("frame-timing: 0 bytes"))
+        .stdout(predicates::str::contains(
+            "lsb (rgba, 2 bits per sample): 9 bytes",
+        ))
+        .stdout(predicates::str::contains(
+            "palette: palette embedding needs",
+        ));
+
+    let args = [
+        "capacity",
+        "-f",
+        "./assets/catgurl.png",
+        "--passphrase",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("passphrase container"))
+        .stdout(predicates::str::contains(
+            "LSB embedding does not support palette",
+        ));
+}
+
+#[test]
+fn test_dry_run_reports_placement_and_size() {
+    let args = [
+        "encode",
+        "-f",
+        "./assets/ryanGosling.png",
+        "-m",
+        "hello",
+        "--dry-run",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "STXT chunk of 48 bytes inserted as chunk 5, after IDAT",
+        ))
+        .stdout(predicates::str::contains(
+            "file size 653265 -> 653325 bytes (+60)",
+        ));
+}
+
+#[test]
+fn test_optimize() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = ["optimize", "-f", "./assets/catgurl.png", "-o", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("4 IDAT chunks merged into 1"))
+        .stdout(predicates::str::contains(
+            "file size 28414 -> 28378 bytes (-36)",
+        ));
+
+    let args = ["inspect", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("1 IDAT chunks of 28207 bytes"));
+}
+
+#[test]
+fn test_encode_optimize_keeps_message() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/animegurl.png",
+        "-m",
+        "smaller than before",
+        "--optimize",
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("optimized: IDAT"))
+        .stdout(predicates::str::contains("file size 34814 ->"));
+
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("smaller than before"));
+}
+
+#[test]
+fn test_encode_optimize_keeps_binding() {
+    let key_dir = tempfile::tempdir().unwrap();
+    let key = key_dir.path().join("signing.key");
+    let key = key.to_str().unwrap();
+    let public_key = format!("{}.pub", key);
+    create_command(&["keygen", "-o", key]).success();
+
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "signed and smaller",
+        "--sign-key",
+        key,
+        "--bind-critical-chunks",
+        "--optimize",
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("4 IDAT chunks merged into 1"));
+
+    let args = ["verify", "-f", output_file, "--verify-key", &public_key];
+    create_command(&args).success();
+}
+
+#[test]
+fn test_pixel_embedding_keeps_compression_fingerprint() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = ["inspect", "-f", "./assets/animegurl.png"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "compression: zlib header 78da, 5 IDAT chunks of 8192, 8192, 8192, 8192, 1893 bytes",
+        ));
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/animegurl.png",
+        "-m",
+        "same old encoder",
+        "--method",
+        "palette",
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "compression fingerprint: kept IDAT split, zlib header, row filters",
+        ));
+
+    let args = ["inspect", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "zlib header 78da, 5 IDAT chunks of 8192",
+        ));
+}
+
+#[test]
+fn test_repair_and_salvage() {
+    let encoded_file = NamedTempFile::new().unwrap();
+    let encoded_file = encoded_file.path().to_str().unwrap();
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "survivor",
+        "-o",
+        encoded_file,
+    ];
+    create_command(&args).success();
+
+    // break an IDAT CRC, wedge garbage in front of the message and cut IEND off
+    let mut bytes = std::fs::read(encoded_file).unwrap();
+    let idat = bytes
+        .windows(4)
+        .position(|window| window == b"IDAT")
+        .unwrap();
+    bytes[idat + 10] ^= 0xff;
+    let stxt = bytes
+        .windows(4)
+        .position(|window| window == b"STXT")
+        .unwrap()
+        - 4;
+    bytes.splice(stxt..stxt, b"GARBAGE!!".iter().copied());
+    bytes.truncate(bytes.len() - 12);
+    let damaged_file = NamedTempFile::new().unwrap();
+    std::fs::write(damaged_file.path(), bytes).unwrap();
+    let damaged_file = damaged_file.path().to_str().unwrap();
+
+    let args = ["decode", "-f", damaged_file];
+    create_command(&args)
+        .failure()
+        .stderr(predicates::str::contains("CRC check failed"));
+
+    let args = ["decode", "-f", damaged_file, "--verify-crc", "warn"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("survivor"));
+
+    let repaired_file = NamedTempFile::new().unwrap();
+    let repaired_file = repaired_file.path().to_str().unwrap();
+    let args = ["repair", "-f", damaged_file, "-o", repaired_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("recomputed CRC of IDAT chunk"))
+        .stdout(predicates::str::contains("skipped 9 unreadable bytes"))
+        .stdout(predicates::str::contains("added the missing IEND chunk"));
+
+    let args = ["decode", "-f", repaired_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("survivor"));
+
+    let args = ["repair", "-f", repaired_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("no problems found"));
+}
+
+#[test]
+fn test_json_output() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "secret message",
+        "-o",
+        output_file,
+        "--format",
+        "json",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::starts_with(
+            r#"{"schema_version":1,"command":"encode","ok":true,"result":{"report":["#,
+        ));
+
+    let args = ["--format", "json", "decode", "-f", output_file];
+    create_command(&args).success().stdout(
+        r#"{"schema_version":1,"command":"decode","ok":true,"result":{"messages":["secret message"]}}"#
+            .to_string()
+            + "\n",
+    );
+
+    let args = ["inspect", "-f", output_file, "--format", "json"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            r#"{"index":0,"chunk_type":"IHDR","length":13,"contents":"#,
+        ))
+        .stdout(predicates::str::contains(r#""zlib_header":"78da""#));
+
+    let args = ["capacity", "-f", "./assets/catgurl.png", "--format", "json"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            r#"{"method":"lsb","channels":"rgb","bits_per_sample":1,"error":{"code":"unsupported_image","#,
+        ))
+        .stdout(predicates::str::contains(
+            r#"{"method":"palette","bytes":"#,
+        ));
+}
+
+#[test]
+fn test_json_error() {
+    let args = ["decode", "-f", "./assets/catgurl.png", "--format", "json"];
+    create_command(&args)
+        .failure()
+        .stdout(
+            r#"{"schema_version":1,"command":"decode","ok":false,"error":{"code":"chunk_type_not_found","message":"Chunk Type `STXT` does not exist."}}"#
+                .to_string()
+                + "\n",
+        );
+}
+
+#[test]
+fn test_exit_codes() {
+    let args = ["decode", "-f", "./assets/does-not-exist.png"];
+    create_command(&args).code(3);
+
+    let args = ["decode", "-f", "./assets/catgurl.png"];
+    create_command(&args).code(4);
+
+    let truncated = NamedTempFile::new().unwrap();
+    let contents = std::fs::read("./assets/catgurl.png").unwrap();
+    std::fs::write(truncated.path(), &contents[..100]).unwrap();
+    let args = ["inspect", "-f", truncated.path().to_str().unwrap()];
+    create_command(&args).code(5);
+
+    let corrupt = NamedTempFile::new().unwrap();
+    let mut contents = contents.clone();
+    contents[40] ^= 1;
+    std::fs::write(corrupt.path(), &contents).unwrap();
+    let args = ["inspect", "-f", corrupt.path().to_str().unwrap()];
+    create_command(&args).code(6);
+
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "secret message",
+        "--passphrase",
+        "hunter2",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file, "--passphrase", "hunter3"];
+    create_command(&args).code(7);
+
+    let message = "x".repeat(100);
+    let args = [
+        "encode",
+        "-f",
+        "./assets/spinner.png",
+        "--method",
+        "frame-timing",
+        "-m",
+        &message,
+        "-o",
+        output_file,
+    ];
+    create_command(&args).code(8);
+
+    // JSON output exits the same way
+    let args = ["decode", "-f", "./assets/catgurl.png", "--format", "json"];
+    create_command(&args).code(4);
+}
+
+#[test]
+fn test_config_file_and_profiles() {
+    let project = tempfile::tempdir().unwrap();
+    let user = tempfile::tempdir().unwrap();
+    std::fs::write(
+        project.path().join("hidepng.toml"),
+        "chunk-type = \"TXTI\"\n\n[profile.early]\nplacement = \"before-image-data\"\n",
+    )
+    .unwrap();
+    let image = std::fs::canonicalize("./assets/catgurl.png").unwrap();
+    let image = image.to_str().unwrap();
+    let output_file = project.path().join("out.png");
+    let output_file = output_file.to_str().unwrap();
+
+    let command = |args: &[&str]| {
+        Command::cargo_bin("hidepng")
+            .unwrap()
+            .current_dir(project.path())
+            .env("XDG_CONFIG_HOME", user.path())
+            .args(args)
+            .assert()
+    };
+
+    command(&["config", "show", "--profile", "early"])
+        .success()
+        .stdout(predicates::str::contains("chunk-type: TXTI"))
+        .stdout(predicates::str::contains("placement: before-image-data"));
+
+    let args = [
+        "encode",
+        "-f",
+        image,
+        "-m",
+        "configured",
+        "-o",
+        output_file,
+        "--profile",
+        "early",
+    ];
+    command(&args).success();
+    command(&["inspect", "-f", output_file])
+        .success()
+        .stdout(predicates::str::contains("   2  TXTI"));
+    command(&["decode", "-f", output_file])
+        .success()
+        .stdout(predicates::str::contains("configured"));
+
+    // flags win over the config file
+    command(&["decode", "-f", output_file, "-c", "STXT"]).code(4);
+
+    command(&["config", "show", "--profile", "late"])
+        .code(1)
+        .stderr(predicates::str::contains("Profile `late` is not defined"));
+}
+
+#[test]
+fn test_extract_and_insert_chunk() {
+    let carrier = NamedTempFile::new().unwrap();
+    let carrier = carrier.path().to_str().unwrap();
+    let chunk_file = NamedTempFile::new().unwrap();
+    let chunk_file = chunk_file.path().to_str().unwrap();
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "moved along",
+        "-o",
+        carrier,
+    ];
+    create_command(&args).success();
+
+    // a whole chunk carries its own type
+    let args = [
+        "extract-chunk",
+        "-f",
+        carrier,
+        "-c",
+        "STXT",
+        "-o",
+        chunk_file,
+        "--whole",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "wrote 66 bytes of STXT chunk 0 of 1 with its CRC",
+        ));
+    let args = [
+        "insert-chunk",
+        "-f",
+        "./assets/animegurl.png",
+        "--chunk-file",
+        chunk_file,
+        "-o",
+        output_file,
+        "--placement",
+        "before-end",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("STXT chunk of 54 bytes inserted"));
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("moved along"));
+
+    // raw data needs a type
+    let args = [
+        "extract-chunk",
+        "-f",
+        carrier,
+        "-c",
+        "STXT",
+        "-o",
+        chunk_file,
+    ];
+    create_command(&args).success();
+    let data = std::fs::read(chunk_file).unwrap();
+    assert!(data.starts_with(b"hPNG"));
+    assert_eq!(&data[11..22], b"moved along");
+    let args = [
+        "insert-chunk",
+        "-f",
+        "./assets/animegurl.png",
+        "--chunk-file",
+        chunk_file,
+        "-c",
+        "TXTI",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file, "-c", "TXTI"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("moved along"));
+
+    let args = [
+        "extract-chunk",
+        "-f",
+        carrier,
+        "-c",
+        "STXT",
+        "--index",
+        "1",
+        "-o",
+        chunk_file,
+    ];
+    create_command(&args)
+        .code(4)
+        .stderr(predicates::str::contains(
+            "Chunk `STXT` number 1 does not exist, the file has 1.",
+        ));
+}
+
+#[test]
+fn test_transplant() {
+    let source = NamedTempFile::new().unwrap();
+    let source = source.path().to_str().unwrap();
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    // an ancillary, safe to copy chunk
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "metadata",
+        "-c",
+        "meTa",
+        "-o",
+        source,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "transplant",
+        "-f",
+        "./assets/animegurl.png",
+        "--source",
+        source,
+        "-o",
+        output_file,
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "copied meTa (51 bytes) after IDAT",
+        ));
+    let args = ["decode", "-f", output_file, "-c", "meTa"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("metadata"));
+
+    let args = [
+        "transplant",
+        "-f",
+        output_file,
+        "--source",
+        "./assets/spinner.png",
+        "-c",
+        "fcTL",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "skipped fcTL: animation chunks belong to the source's frames",
+        ));
+}
+
+#[test]
+fn test_diff() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+
+    let args = ["diff", "./assets/catgurl.png", "./assets/catgurl.png"];
+    create_command(&args)
+        .success()
+        .stdout("7 chunks unchanged\n");
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "--method",
+        "palette",
+        "-m",
+        "hello there",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    let args = ["diff", "./assets/catgurl.png", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "modified PLTE 0: chunk 1, 102 bytes",
+        ))
+        .stdout(predicates::str::contains("2 chunks unchanged"))
+        .stdout(predicates::str::contains(
+            "pixels: 684 samples of 228 pixels changed",
+        ));
+
+    let args = [
+        "diff",
+        "./assets/catgurl.png",
+        "./assets/animegurl.png",
+        "--format",
+        "json",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(r#""identical":false"#))
+        .stdout(predicates::str::contains(
+            r#""pixels":{"status":"incomparable","reason":"sizes differ, 360x479 and 900x698"}"#,
+        ));
+}
+
+#[test]
+fn test_dump() {
+    let args = [
+        "dump",
+        "-f",
+        "./assets/catgurl.png",
+        "-c",
+        "IHDR",
+        "--annotate",
+    ];
+    create_command(&args).success().stdout(
+        "chunk 0 at byte 8: IHDR, 13 bytes, crc 4af4b22a, critical, public, unsafe to copy\n\
+         \x20 = 360x479, 8-bit indexed, non-interlaced\n\
+         \x20 00000000  00 00 01 68 00 00 01 df  08 03 00 00 00           |...h.........|\n",
+    );
+
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+    let key_dir = tempfile::tempdir().unwrap();
+    let key_file = key_dir.path().join("signing.key");
+    let key_file = key_file.to_str().unwrap();
+    create_command(&["keygen", "-o", key_file]).success();
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "hello dump",
+        "--sign-key",
+        key_file,
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+
+    let args = [
+        "dump",
+        "-f",
+        output_file,
+        "-c",
+        "STXT",
+        "--index",
+        "0",
+        "--max-bytes",
+        "16",
+        "--annotate",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "= hidepng payload v2, 10 byte message, signed by",
+        ))
+        .stdout(predicates::str::contains("|hPNG.......hello|"))
+        .stdout(predicates::str::contains("... 133 more bytes"));
+
+    let args = ["dump", "-f", output_file, "-c", "tEXt"];
+    create_command(&args).code(4);
+}
+
+#[test]
+fn test_manifest_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifest = dir.path().join("catgurl.json");
+    let manifest = manifest.to_str().unwrap();
+    let output_file = dir.path().join("rebuilt.png");
+    let output_file = output_file.to_str().unwrap();
+
+    let args = [
+        "manifest",
+        "export",
+        "-f",
+        "./assets/catgurl.png",
+        "-o",
+        manifest,
+        "--data-dir",
+        "data",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "wrote manifest of 7 chunks and 4 data files",
+        ));
+    assert!(dir.path().join("data/002-IDAT.bin").is_file());
+
+    let mut escaping = args;
+    escaping[7] = "../data";
+    create_command(&escaping)
+        .code(1)
+        .stderr(predicates::str::contains(
+            "must be a relative path inside the manifest's directory",
+        ));
+
+    let args = ["manifest", "build", "-f", manifest, "-o", output_file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("built 7 chunks, 28414 bytes"));
+    assert_eq!(
+        std::fs::read(output_file).unwrap(),
+        std::fs::read("./assets/catgurl.png").unwrap()
+    );
+
+    std::fs::write(
+        manifest,
+        r#"{"manifest_version": 1, "chunks": [{"type": "IHDR"}]}"#,
+    )
+    .unwrap();
+    let args = ["manifest", "build", "-f", manifest, "-o", output_file];
+    create_command(&args)
+        .code(1)
+        .stderr(predicates::str::contains("Invalid manifest"));
+}
+
+#[test]
+fn test_payload_framing() {
+    let output_file = NamedTempFile::new().unwrap();
+    let output_file = output_file.path().to_str().unwrap();
+    let message = "fragments of a compressed message ".repeat(8);
+
+    let mut args = vec![
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        &message,
+        "--compress",
+        "--fragment-size",
+        "64",
+        "-o",
+        output_file,
+        "--dry-run",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "91 bytes in 7 STXT chunks of up to 64 bytes inserted as chunks 6 to 12, after IDAT",
+        ));
+    args.pop();
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(format!("{}\n", message));
+    let args = ["dump", "-f", output_file, "-c", "STXT", "--annotate"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "= hidepng payload v2, fragment 7 of 7",
+        ));
+
+    // the same message stored again gets fragments of its own
+    let args = [
+        "encode",
+        "-f",
+        output_file,
+        "-m",
+        &message,
+        "--compress",
+        "--fragment-size",
+        "64",
+    ];
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(format!("{}\n{}\n", message, message));
+
+    // removing without a name drops every fragment of the first message
+    let removed_file = NamedTempFile::new().unwrap();
+    let removed_file = removed_file.path().to_str().unwrap();
+    std::fs::copy(output_file, removed_file).unwrap();
+    let args = ["remove", "-f", removed_file, "-c", "STXT"];
+    create_command(&args).success();
+    let args = ["decode", "-f", removed_file];
+    create_command(&args)
+        .success()
+        .stdout(format!("{}\n", message))
+        .stderr("");
+    let args = ["dump", "-f", removed_file, "-c", "STXT", "--annotate"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::function::function(|dump: &str| {
+            dump.matches("fragment 1 of 7").count() == 1
+                && dump.matches("fragment 7 of 7").count() == 1
+        }));
+
+    // a forged payload is skipped without hiding the others
+    let chunk_file = NamedTempFile::new().unwrap();
+    std::fs::write(chunk_file.path(), b"hPNG\x02forged").unwrap();
+    let args = [
+        "insert-chunk",
+        "-f",
+        output_file,
+        "--chunk-file",
+        chunk_file.path().to_str().unwrap(),
+        "-c",
+        "STXT",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout(format!("{}\n{}\n", message, message))
+        .stderr(predicates::str::contains(
+            "Warning: skipped a payload that can't be read",
+        ));
+
+    let args = [
+        "encode",
+        "-f",
+        "./assets/catgurl.png",
+        "-m",
+        "hi",
+        "--method",
+        "lsb",
+        "--fragment-size",
+        "64",
+    ];
+    create_command(&args).code(1);
+
+    // a chunk that only shares the type is no message
+    let chunk_file = NamedTempFile::new().unwrap();
+    std::fs::write(chunk_file.path(), "not a payload").unwrap();
+    let args = [
+        "insert-chunk",
+        "-f",
+        "./assets/catgurl.png",
+        "--chunk-file",
+        chunk_file.path().to_str().unwrap(),
+        "-c",
+        "STXT",
+        "-o",
+        output_file,
+    ];
+    create_command(&args).success();
+    let args = ["decode", "-f", output_file];
+    create_command(&args)
+        .success()
+        .stdout("not a payload\n")
+        .stderr(predicates::str::contains("isn't a hidepng payload"));
+    let args = ["decode", "-f", output_file, "--name", "notes"];
+    create_command(&args).code(4);
+}
+
+#[test]
+fn test_named_messages() {
+    let file = NamedTempFile::new().unwrap();
+    std::fs::copy("./assets/catgurl.png", file.path()).unwrap();
+    let file = file.path().to_str().unwrap();
+
+    let args = ["encode", "-f", file, "-m", "buy milk", "--name", "notes"];
+    create_command(&args).success();
+    let args = [
+        "encode",
+        "-f",
+        file,
+        "-m",
+        "call the vet",
+        "--name",
+        "todo",
+        "--fragment-size",
+        "60",
+    ];
+    create_command(&args).success();
+    let args = ["encode", "-f", file, "-m", "again", "--name", "notes"];
+    create_command(&args)
+        .code(1)
+        .stderr(predicates::str::contains("already exists"));
+
+    let args = ["list", "-f", file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "notes (chunk 13): hidepng payload v2, 8 byte message `notes`",
+        ))
+        .stdout(predicates::str::contains("todo (chunks 6, 7,"));
+    let args = ["decode", "-f", file, "--name", "todo"];
+    create_command(&args).success().stdout("call the vet\n");
+
+    let args = [
+        "update",
+        "-f",
+        file,
+        "--name",
+        "notes",
+        "-m",
+        "buy oat milk",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains(
+            "replaced `notes` in 1 STXT chunks",
+        ));
+    let args = ["decode", "-f", file, "--name", "notes"];
+    create_command(&args).success().stdout("buy oat milk\n");
+
+    let args = ["remove", "-f", file, "--name", "todo"];
+    create_command(&args).success();
+    let args = ["decode", "-f", file];
+    create_command(&args).success().stdout("buy oat milk\n");
+    let args = ["decode", "-f", file, "--name", "todo"];
+    create_command(&args).code(4);
+    let args = ["update", "-f", file, "--name", "todo", "-m", "gone"];
+    create_command(&args).code(4);
+
+    // a sealed name is only found with its passphrase
+    let sealed = [
+        "encode",
+        "-f",
+        file,
+        "-m",
+        "the key is under the mat",
+        "--name",
+        "secret",
+        "--passphrase",
+        "hunter2",
+    ];
+    create_command(&sealed).success();
+    create_command(&sealed)
+        .code(1)
+        .stderr(predicates::str::contains("already exists"));
+    let args = ["list", "-f", file];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("secret").not());
+    let args = ["list", "-f", file, "--passphrase", "hunter2"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("secret (chunk 6):"))
+        .stdout(predicates::str::contains("sealed with a passphrase"));
+
+    let args = [
+        "update",
+        "-f",
+        file,
+        "--name",
+        "secret",
+        "--passphrase",
+        "hunter2",
+        "-m",
+        "the key is in the shed",
+        "--placement",
+        "before-end",
+    ];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("inserted as chunk 7, after STXT"))
+        .stderr(predicates::str::contains(
+            "Warning: resealed `secret` alone, any other messages in its container's slots are lost",
+        ));
+    let args = [
+        "decode",
+        "-f",
+        file,
+        "--passphrase",
+        "hunter2",
+        "--name",
+        "secret",
+    ];
+    create_command(&args)
+        .success()
+        .stdout("the key is in the shed\n");
+
+    // decoys given again are resealed alongside
+    let args = [
+        "update",
+        "-f",
+        file,
+        "--name",
+        "secret",
+        "--passphrase",
+        "hunter2",
+        "-m",
+        "the key is in the shed",
+        "--decoy-message",
+        "buy milk",
+        "--decoy-passphrase",
+        "decoy",
+    ];
+    create_command(&args).success().stderr("");
+    let args = ["list", "-f", file, "--passphrase", "decoy"];
+    create_command(&args)
+        .success()
+        .stdout(predicates::str::contains("8 byte message, sealed"));
+
+    let args = [
+        "remove",
+        "-f",
+        file,
+        "--name",
+        "secret",
+        "--passphrase",
+        "hunter2",
+    ];
+    create_command(&args)
+        .success()
+        .stderr(predicates::str::contains(
+            "Warning: removed the whole container sealing `secret`",
+        ));
+    let args = ["list", "-f", file];
+    create_command(&args)
+        .success()
+        .stdout("notes (chunk 6): hidepng payload v2, 12 byte message `notes`\n");
+}